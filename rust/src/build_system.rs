@@ -1,5 +1,6 @@
+use crate::check::{Normalization, Order};
 use crate::helpers::array::{sort, transform};
-use crate::helpers::pep508::{format_requirement, get_canonic_requirement_name};
+use crate::helpers::pep508::{format_requirement_or_keep, get_canonic_requirement_name};
 use crate::helpers::table::{for_entries, reorder_table_keys, Tables};
 
 pub fn fix(tables: &Tables, keep_full_version: bool) {
@@ -10,7 +11,7 @@ pub fn fix(tables: &Tables, keep_full_version: bool) {
     let table = &mut table_element.unwrap().first().unwrap().borrow_mut();
     for_entries(table, &mut |key, entry| match key.as_str() {
         "requires" => {
-            transform(entry, &|s| format_requirement(s, keep_full_version));
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
             sort(entry, |e| get_canonic_requirement_name(e).to_lowercase());
         }
         "backend-path" => {
@@ -21,6 +22,20 @@ pub fn fix(tables: &Tables, keep_full_version: bool) {
     reorder_table_keys(table, &["", "build-backend", "requires", "backend-path"]);
 }
 
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| path == "build-system.requires",
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "build-system.backend-path",
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
 #[cfg(test)]
 mod tests {
     use indoc::indoc;
@@ -35,7 +50,7 @@ mod tests {
     fn evaluate(start: &str, keep_full_version: bool) -> String {
         let root_ast = parse(start).into_syntax().clone_for_update();
         let count = root_ast.children_with_tokens().count();
-        let tables = Tables::from_ast(&root_ast);
+        let tables = Tables::from_ast(&root_ast, false);
         fix(&tables, keep_full_version);
         let entries = tables
             .table_set
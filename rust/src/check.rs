@@ -0,0 +1,495 @@
+use std::collections::BTreeMap;
+
+use similar::TextDiff;
+use taplo::dom::Node;
+use taplo::parser::parse;
+
+use crate::helpers::dom::lookup;
+use crate::Settings;
+
+/// Ensure formatting the output again does not change it, the formatter must reach a fixed point in one run.
+pub fn ensure_stable(formatted: &str, formatted_again: &str) -> Result<(), String> {
+    if formatted == formatted_again {
+        return Ok(());
+    }
+    let diff = TextDiff::from_lines(formatted, formatted_again)
+        .unified_diff()
+        .header("first run", "second run")
+        .to_string();
+    Err(format!(
+        "formatting is not stable, a second run changes the output:\n{diff}"
+    ))
+}
+
+/// Ensure the formatted document holds the same data as the original one. Differences the fixers make on purpose, as
+/// described by their `rules`, are ignored, and so are the order of keys and tables and whitespace.
+pub fn ensure_equivalent(
+    original: &str,
+    formatted: &str,
+    opt: &Settings,
+    rules: &[&[Normalization]],
+) -> Result<(), String> {
+    let (original, formatted) = (parse(original).into_dom(), parse(formatted).into_dom());
+    let (mut before, mut after) = (
+        Normalize::new(opt, rules, &original),
+        Normalize::new(opt, rules, &formatted),
+    );
+    before.flatten(&original, "");
+    after.flatten(&formatted, "");
+    let mut changes = Vec::<String>::new();
+    // the values moved elsewhere, by their new path: the whole value, or the elements of the array there
+    let mut targets = BTreeMap::<String, (Option<String>, Vec<String>)>::new();
+    for (path, value) in &before.values {
+        match after.values.remove(path) {
+            None => match before.moves.remove(path).flatten() {
+                Some(moves) => {
+                    for moved in moves {
+                        let (whole, elements) = targets.entry(moved.path).or_default();
+                        if moved.element {
+                            elements.push(moved.value);
+                        } else {
+                            whole.get_or_insert(moved.value);
+                        }
+                    }
+                }
+                None => changes.push(format!("{path} was removed")),
+            },
+            Some(new) if new != *value => changes.push(format!("{path} changed from {value} to {new}")),
+            Some(_) => {}
+        }
+    }
+    for (path, (whole, elements)) in targets {
+        let text = whole.unwrap_or_else(|| format!("[{}]", elements.join(", ")));
+        let expected = lookup(&parse(&format!("v = {text}")).into_dom(), &["v"])
+            .map_or_else(|| String::from("<invalid>"), |node| after.render(&node, &path));
+        match after.values.remove(&path) {
+            None => changes.push(format!("{path} was not added")),
+            Some(new) if new != expected => changes.push(format!("{path} changed from {expected} to {new}")),
+            Some(_) => {}
+        }
+    }
+    let normalize = after;
+    changes.extend(
+        normalize
+            .values
+            .keys()
+            .filter(|path| !normalize.rule(path).added)
+            .map(|path| format!("{path} was added")),
+    );
+    if changes.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "formatting would change the meaning of the document:\n{}",
+            changes.join("\n")
+        ))
+    }
+}
+
+/// A rewrite a fixer makes on purpose, which the equivalence check ignores. Each fixer lists these next to its `fix`.
+pub struct Normalization {
+    /// Whether the rule covers the value at the dotted path, array elements (and tables within arrays) share the path
+    /// of the array.
+    pub applies: fn(&str, &Settings) -> bool,
+    /// The form strings are compared in.
+    pub string: fn(&str, &Settings) -> String,
+    /// The order array elements are compared in.
+    pub order: Order,
+    /// The form the keys of a table are compared in.
+    pub key: fn(&str) -> String,
+    /// Whether the fixer generates (and removes) the array element on its own.
+    pub generated: fn(&Node) -> bool,
+    /// Whether the fixer may add the value.
+    pub added: bool,
+    /// The arguments of a string the fixer may split into an array of them, `None` if it cannot be split.
+    pub split: Option<Split>,
+    /// Where the fixer may move the value to, a value it moves is compared there.
+    pub moved: Option<Move>,
+}
+
+impl Normalization {
+    /// Compare values as they are, the base the rules of the fixers build on.
+    pub const NONE: Self = Self {
+        applies: |_, _| false,
+        string: |value, _| String::from(value),
+        order: Order::Kept,
+        key: |key| String::from(key),
+        generated: |_| false,
+        added: false,
+        split: None,
+        moved: None,
+    };
+}
+
+/// Split a string into arguments, `None` when it cannot be split.
+pub type Split = fn(&str) -> Option<Vec<String>>;
+
+/// Where the value at the dotted path, under the key, moves to within the document at the root; `None` if it cannot
+/// move, and no places if it may go without a trace.
+pub type Move = fn(&str, &str, &Node, &Node) -> Option<Vec<Moved>>;
+
+/// A place a value moves to.
+pub struct Moved {
+    /// The dotted path of the place.
+    pub path: String,
+    /// The value there, written as TOML.
+    pub value: String,
+    /// Whether the value is one element of the array there, rather than all of it.
+    pub element: bool,
+}
+
+/// The order array elements are compared in.
+pub enum Order {
+    /// The order of the elements carries meaning, the fixer keeps it.
+    Kept,
+    /// The fixer sorts the elements.
+    Any,
+    /// The fixer sorts the elements if all of them together pass the check, otherwise keeps their order.
+    AnyIf(fn(&[Node]) -> bool),
+    /// The fixer sorts the elements after the first one.
+    AnyAfterFirst,
+    /// The fixer moves the elements matching to the end, the others keep their order.
+    Last(fn(&Node) -> bool),
+}
+
+struct Normalize<'a> {
+    opt: &'a Settings,
+    rules: &'a [&'a [Normalization]],
+    root: &'a Node,
+    /// The value of each key not holding a table, by its dotted path.
+    values: BTreeMap<String, String>,
+    /// Where the values the fixers may move go, by their dotted path.
+    moves: BTreeMap<String, Option<Vec<Moved>>>,
+}
+
+impl<'a> Normalize<'a> {
+    const fn new(opt: &'a Settings, rules: &'a [&'a [Normalization]], root: &'a Node) -> Self {
+        Self {
+            opt,
+            rules,
+            root,
+            values: BTreeMap::new(),
+            moves: BTreeMap::new(),
+        }
+    }
+
+    /// The rule covering the value at the dotted path.
+    fn rule(&self, path: &str) -> &'a Normalization {
+        self.rules
+            .iter()
+            .flat_map(|rules| rules.iter())
+            .find(|rule| (rule.applies)(path, self.opt))
+            .unwrap_or(&Normalization::NONE)
+    }
+
+    /// Collect the value of each key not holding a table (or one moved as a whole), by its dotted path.
+    fn flatten(&mut self, node: &Node, prefix: &str) {
+        if let Node::Table(table) = node {
+            let normalize_key = self.rule(prefix).key;
+            for (key, value) in table.entries().read().iter() {
+                let key = normalize_key(key.value());
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                let rule = self.rule(&path);
+                if let Some(moved) = rule.moved {
+                    self.moves.insert(path.clone(), moved(&path, &key, value, self.root));
+                } else if value.is_table() {
+                    self.flatten(value, &path);
+                    continue;
+                }
+                if let Some(args) = rule
+                    .split
+                    .and_then(|split| value.as_str().and_then(|s| split(s.value())))
+                {
+                    // a string of arguments may be split into an array of them
+                    self.values.insert(path, list(args.iter().map(|a| format!("{a:?}"))));
+                } else {
+                    let rendered = self.render(value, &path);
+                    self.values.insert(path, rendered);
+                }
+            }
+        }
+    }
+
+    /// A representation of the value that is equal for values that differ only by intended rewrites.
+    fn render(&self, node: &Node, path: &str) -> String {
+        let rule = self.rule(path);
+        match node {
+            Node::Table(table) => {
+                let mut entries = table
+                    .entries()
+                    .read()
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = (rule.key)(key.value());
+                        format!("{key:?} = {}", self.render(value, &format!("{path}.{key}")))
+                    })
+                    .collect::<Vec<String>>();
+                entries.sort();
+                format!("{{ {} }}", entries.join(", "))
+            }
+            Node::Array(array) => {
+                let items = array.items().read();
+                let mut rendered = items
+                    .iter()
+                    .filter(|item| !(rule.generated)(item))
+                    .map(|item| (item, self.render(item, path)))
+                    .collect::<Vec<(&Node, String)>>();
+                match rule.order {
+                    Order::Kept => {}
+                    Order::Any => rendered.sort_by(|(_, a), (_, b)| a.cmp(b)),
+                    Order::AnyIf(sorted) => {
+                        if sorted(&items) {
+                            rendered.sort_by(|(_, a), (_, b)| a.cmp(b));
+                        }
+                    }
+                    Order::AnyAfterFirst => {
+                        if let Some((_, rest)) = rendered.split_first_mut() {
+                            rest.sort_by(|(_, a), (_, b)| a.cmp(b));
+                        }
+                    }
+                    Order::Last(is_last) => rendered.sort_by_key(|(item, _)| is_last(item)),
+                }
+                list(rendered.into_iter().map(|(_, value)| value))
+            }
+            Node::Str(value) => format!("{:?}", (rule.string)(value.value(), self.opt)),
+            Node::Bool(value) => value.value().to_string(),
+            Node::Integer(value) => value.value().to_string(),
+            Node::Float(value) => value.value().to_string(),
+            Node::Date(value) => value.value().to_string(),
+            Node::Invalid(_) => String::from("<invalid>"),
+        }
+    }
+}
+
+fn list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<String>>().join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+
+    use crate::check::{ensure_equivalent, ensure_stable};
+    use crate::{Settings, NORMALIZATIONS};
+
+    #[rstest]
+    #[case::same("a = 1\n", "a = 1\n", Ok(()))]
+    #[case::changed(
+        "a = 1\nb = 2\n",
+        "b = 2\na = 1\n",
+        Err(String::from(indoc! {"
+        formatting is not stable, a second run changes the output:
+        --- first run
+        +++ second run
+        @@ -1,2 +1,2 @@
+        +b = 2
+         a = 1
+        -b = 2
+        "}))
+    )]
+    fn test_ensure_stable(#[case] first: &str, #[case] second: &str, #[case] expected: Result<(), String>) {
+        assert_eq!(ensure_stable(first, second), expected);
+    }
+
+    #[rstest]
+    #[case::intended_rewrites(
+        indoc ! {r#"
+    [project]
+    name = "A_b"
+    description = "  some   text "
+    dependencies = ["b>=1.0.0", "a"]
+    urls.docs = "x"
+    [tool.pytest.ini_options]
+    addopts = "-ra -k 'a or b'"
+    [tool.poetry.dependencies]
+    PyYAML = "^6"
+    [tool.tox.env.lint]
+    deps = ["ruff==0.6.0", "-r requirements.txt"]
+    [tool.hatch.envs.test]
+    dependencies = ["pytest>=8.0.0"]
+    [[tool.cibuildwheel.overrides]]
+    test-requires = ["numpy>=1.26.0"]
+    [tool.uv]
+    dev-dependencies = ["b c", "-e ./a", "A>=1.0"]
+    [[tool.uv.index]]
+    name = "b"
+    default = true
+    [[tool.uv.index]]
+    name = "a"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "a-b"
+    description = "some text"
+    classifiers = ["Programming Language :: Python :: 3 :: Only"]
+    dependencies = ["a", "b>=1"]
+    [project.urls]
+    docs = "x"
+    [tool.pytest.ini_options]
+    addopts = ["-ra", "-k", "a or b"]
+    [tool.poetry.dependencies]
+    pyyaml = "^6"
+    [tool.tox.env.lint]
+    deps = ["ruff==0.6", "-r requirements.txt"]
+    [tool.hatch.envs.test]
+    dependencies = ["pytest>=8"]
+    [[tool.cibuildwheel.overrides]]
+    test-requires = ["numpy>=1.26"]
+    [tool.uv]
+    dev-dependencies = ["-e ./a", "a>=1", "b c"]
+    [[tool.uv.index]]
+    name = "a"
+    [[tool.uv.index]]
+    name = "b"
+    default = true
+    "#},
+        Ok(())
+    )]
+    #[case::reordered(
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    filterwarnings = ["error", "ignore::DeprecationWarning"]
+    [tool.tox.env_run_base]
+    commands = [["pytest"], ["coverage", "report"]]
+    [tool.tox.env."py3.12"]
+    pass_env = ["B", "A"]
+    set_env.pass_env = ["B", "A"]
+    [[tool.cibuildwheel.overrides]]
+    select = "cp39-*"
+    [[tool.cibuildwheel.overrides]]
+    select = "*"
+    [[tool.mypy.overrides]]
+    module = "b"
+    [[tool.mypy.overrides]]
+    module = "a.*.c"
+    "#},
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    filterwarnings = ["ignore::DeprecationWarning", "error"]
+    [tool.tox.env_run_base]
+    commands = [["coverage", "report"], ["pytest"]]
+    [tool.tox.env."py3.12"]
+    pass_env = ["A", "B"]
+    set_env.pass_env = ["A", "B"]
+    [[tool.cibuildwheel.overrides]]
+    select = "*"
+    [[tool.cibuildwheel.overrides]]
+    select = "cp39-*"
+    [[tool.mypy.overrides]]
+    module = "a.*.c"
+    [[tool.mypy.overrides]]
+    module = "b"
+    "#},
+        Err(String::from(indoc! {r#"
+        formatting would change the meaning of the document:
+        tool.cibuildwheel.overrides changed from [{ "select" = "cp39-*" }, { "select" = "*" }] to [{ "select" = "*" }, { "select" = "cp39-*" }]
+        tool.mypy.overrides changed from [{ "module" = "b" }, { "module" = "a.*.c" }] to [{ "module" = "a.*.c" }, { "module" = "b" }]
+        tool.pytest.ini_options.filterwarnings changed from ["error", "ignore::DeprecationWarning"] to ["ignore::DeprecationWarning", "error"]
+        tool.tox.env.py3.12.set_env.pass_env changed from ["B", "A"] to ["A", "B"]
+        tool.tox.env_run_base.commands changed from [["pytest"], ["coverage", "report"]] to [["coverage", "report"], ["pytest"]]"#}))
+    )]
+    #[case::lost_and_altered(
+        indoc ! {r#"
+    [project]
+    name = "a"
+    version = "1"
+    urls.docs = "x"
+    [[tool.uv.index]]
+    name = "b"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "a"
+    version = 1
+    [[tool.uv.index]]
+    url = "b"
+    [tool.other]
+    a = 1
+    "#},
+        Err(String::from(indoc! {r#"
+        formatting would change the meaning of the document:
+        project.urls.docs was removed
+        project.version changed from "1" to 1
+        tool.uv.index changed from [{ "name" = "b" }] to [{ "url" = "b" }]
+        tool.other.a was added"#}))
+    )]
+    fn test_ensure_equivalent(#[case] original: &str, #[case] formatted: &str, #[case] expected: Result<(), String>) {
+        let opt = Settings {
+            split_pytest_addopts: true,
+            ..Settings::default()
+        };
+        assert_eq!(ensure_equivalent(original, formatted, &opt, NORMALIZATIONS), expected);
+    }
+
+    #[rstest]
+    #[case::migrated(
+        indoc ! {r#"
+    [tool.poetry]
+    name = "demo"
+    license = "MIT"
+    authors = ["Ann <ann@example.com>"]
+    homepage = "https://example.com"
+    [tool.poetry.dependencies]
+    python = "^3.9"
+    requests = "^2.0"
+    numpy = { version = "^1.0", optional = true }
+    [tool.poetry.extras]
+    fast = ["numpy"]
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "demo"
+    license = { text = "MIT" }
+    authors = [{ name = "Ann", email = "ann@example.com" }]
+    requires-python = ">=3.9,<4.0"
+    dependencies = ["requests>=2,<3"]
+    optional-dependencies.fast = ["numpy>=1,<2"]
+    urls.Homepage = "https://example.com"
+    "#},
+        Ok(())
+    )]
+    #[case::migrated_wrong(
+        indoc ! {r#"
+    [tool.poetry]
+    name = "demo"
+    authors = ["Ann <ann@example.com>"]
+    [tool.poetry.dependencies]
+    requests = "^2.0"
+    flask = { git = "https://example.com/flask.git" }
+    numpy = { version = "^1.0", optional = true }
+    [tool.poetry.extras]
+    fast = ["numpy"]
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "other"
+    authors = [{ name = "Ann <ann@example.com>" }]
+    dependencies = ["requests>=2"]
+    [tool.poetry.extras]
+    fast = ["numpy"]
+    "#},
+        Err(String::from(indoc! {r#"
+        formatting would change the meaning of the document:
+        tool.poetry.dependencies.flask was removed
+        project.authors changed from [{ "email" = "ann@example.com", "name" = "Ann" }] to [{ "name" = "Ann <ann@example.com>" }]
+        project.dependencies changed from ["requests>=2,<3"] to ["requests>=2"]
+        project.name changed from "demo" to "other"
+        project.optional-dependencies.fast was not added"#}))
+    )]
+    fn test_ensure_equivalent_migrated(
+        #[case] original: &str,
+        #[case] formatted: &str,
+        #[case] expected: Result<(), String>,
+    ) {
+        let opt = Settings {
+            migrate_poetry: true,
+            ..Settings::default()
+        };
+        assert_eq!(ensure_equivalent(original, formatted, &opt, NORMALIZATIONS), expected);
+    }
+}
@@ -0,0 +1,180 @@
+use taplo::syntax::SyntaxNode;
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, transform};
+use crate::helpers::pep508::format_requirement_or_keep;
+use crate::helpers::table::{for_entries, reorder_tables, Tables};
+
+pub fn fix(tables: &mut Tables, keep_full_version: bool) {
+    let mut names = vec![
+        String::from("tool.cibuildwheel"),
+        String::from("tool.cibuildwheel.overrides"),
+    ];
+    names.extend(PLATFORMS.iter().map(|p| format!("tool.cibuildwheel.{p}")));
+    for name in &names {
+        // the overrides apply in sequence, so only their content is fixed, never their order
+        for table_element in tables.get(name).unwrap_or_default() {
+            for_entries(&table_element.borrow(), &mut |key, entry| {
+                fix_setting(&key, entry, keep_full_version);
+            });
+        }
+    }
+    // the platform tables come first, followed by the overrides (in the order written)
+    let mut order = PLATFORMS
+        .iter()
+        .map(|p| format!("tool.cibuildwheel.{p}"))
+        .filter(|name| tables.header_to_pos.get(name).is_some_and(|p| p.len() == 1))
+        .collect::<Vec<String>>();
+    order.push(String::from("tool.cibuildwheel.overrides"));
+    reorder_tables(tables, &order);
+}
+
+/// Fix the setting `key`, which may be prefixed when written as a dotted key of a parent table.
+fn fix_setting(key: &str, entry: &SyntaxNode, keep_full_version: bool) {
+    match key.rsplit('.').next().unwrap_or(key) {
+        // the selectors match identifiers such as cp313-manylinux_x86_64, in any order
+        name if SELECTORS.contains(&name) => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        // may also be given as a single (space separated) string, that is kept as is
+        "test-requires" => {
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
+        }
+        // the variables of the environment may refer to the ones set before, so these keep their order
+        _ => {}
+    }
+}
+
+/// The settings selecting builds by their identifier.
+const SELECTORS: &[&str] = &["build", "skip", "test-skip", "select"];
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| path.starts_with("tool.cibuildwheel.") && path.rsplit('.').next() == Some("test-requires"),
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| {
+            path.starts_with("tool.cibuildwheel.")
+                && path.rsplit('.').next().is_some_and(|key| SELECTORS.contains(&key))
+        },
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
+const PLATFORMS: &[&str] = &["linux", "macos", "windows", "ios", "pyodide"];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::cibuildwheel::fix;
+    use crate::helpers::table::Tables;
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables, false);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_cibuildwheel(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::settings(
+        indoc ! {r#"
+    [tool.cibuildwheel]
+    skip = ["pp*", "*-musllinux_i686", "cp38-*"]
+    test-requires = ["pytest>=8.0.0", "Coverage[toml]"]
+    environment = { B = "1", A = "$B" }
+    test-command = "pytest {project}/tests"
+    "#},
+        indoc ! {r#"
+    [tool.cibuildwheel]
+    skip = [
+      "*-musllinux_i686",
+      "cp38-*",
+      "pp*",
+    ]
+    test-requires = [
+      "pytest>=8",
+      "coverage[toml]",
+    ]
+    environment = { B = "1", A = "$B" }
+    test-command = "pytest {project}/tests"
+    "#},
+    )]
+    #[case::platforms_and_overrides(
+        indoc ! {r#"
+    [tool.cibuildwheel.windows]
+    archs = ["AMD64"]
+
+    [tool.cibuildwheel.linux]
+    archs = ["x86_64", "aarch64"]
+
+    [[tool.cibuildwheel.overrides]]
+    select = ["cp39-*", "cp310-*"]
+    test-requires = ["numpy>=1.26.0"]
+
+    [[tool.cibuildwheel.overrides]]
+    select = "*-musllinux*"
+    before-all = "apk add openssl-dev"
+
+    [tool.cibuildwheel.macos]
+    archs = ["universal2"]
+    "#},
+        indoc ! {r#"
+    [tool.cibuildwheel.linux]
+    archs = [
+      "x86_64",
+      "aarch64",
+    ]
+
+    [tool.cibuildwheel.macos]
+    archs = [
+      "universal2",
+    ]
+
+    [tool.cibuildwheel.windows]
+    archs = [
+      "AMD64",
+    ]
+
+    [[tool.cibuildwheel.overrides]]
+    select = [
+      "cp39-*",
+      "cp310-*",
+    ]
+    test-requires = [
+      "numpy>=1.26",
+    ]
+
+    [[tool.cibuildwheel.overrides]]
+    select = "*-musllinux*"
+    before-all = "apk add openssl-dev"
+    "#},
+    )]
+    fn test_format_cibuildwheel(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+}
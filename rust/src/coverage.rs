@@ -0,0 +1,229 @@
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, string_values, transform};
+use crate::helpers::table::{collapse_sub_tables, for_entries, reorder_table_keys, Tables};
+
+pub fn fix(tables: &mut Tables) {
+    collapse_sub_tables(tables, "tool.coverage");
+    let table_element = tables.get("tool.coverage");
+    if table_element.is_none() {
+        return;
+    }
+    let table = &mut table_element.unwrap().first().unwrap().borrow_mut();
+    for_entries(table, &mut |key, entry| match key.as_str() {
+        name if SORTED.contains(&name) => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        _ => {
+            if key.starts_with("paths.") {
+                // the first path is where the data is remapped to, the rest are the alternative locations
+                transform(entry, &|s| String::from(s));
+                let canonical = string_values(entry).into_iter().next().unwrap_or_default();
+                sort(entry, |s| {
+                    if s == canonical {
+                        String::new()
+                    } else {
+                        s.to_lowercase()
+                    }
+                });
+            }
+        }
+    });
+    reorder_table_keys(
+        table,
+        &[
+            "",
+            "run.source",
+            "run.source_pkgs",
+            "run.include",
+            "run.omit",
+            "run.branch",
+            "run.parallel",
+            "run.concurrency",
+            "run.context",
+            "run.dynamic_context",
+            "run.data_file",
+            "run.relative_files",
+            "run.command_line",
+            "run.cover_pylib",
+            "run.timid",
+            "run.core",
+            "run.patch",
+            "run.plugins",
+            "run.debug",
+            "run.debug_file",
+            "run.disable_warnings",
+            "run.sigterm",
+            "run",
+            "paths",
+            "report.fail_under",
+            "report.precision",
+            "report.show_missing",
+            "report.skip_covered",
+            "report.skip_empty",
+            "report.sort",
+            "report.format",
+            "report.include",
+            "report.omit",
+            "report.exclude_lines",
+            "report.exclude_also",
+            "report.partial_branches",
+            "report.partial_also",
+            "report.ignore_errors",
+            "report.include_namespace_packages",
+            "report",
+            "html.directory",
+            "html.title",
+            "html.extra_css",
+            "html.show_contexts",
+            "html.skip_covered",
+            "html.skip_empty",
+            "html",
+            "xml.output",
+            "xml.package_depth",
+            "xml",
+            "json.output",
+            "json.pretty_print",
+            "json.show_contexts",
+            "json",
+            "lcov.output",
+            "lcov.line_checksums",
+            "lcov",
+        ],
+    );
+}
+
+/// The settings holding lists of patterns or packages, whose order carries no meaning.
+const SORTED: &[&str] = &[
+    "run.source",
+    "run.source_pkgs",
+    "run.omit",
+    "run.include",
+    "run.plugins",
+    "report.omit",
+    "report.include",
+    "report.exclude_lines",
+    "report.exclude_also",
+    "report.partial_branches",
+    "report.partial_also",
+];
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| {
+            path.strip_prefix("tool.coverage.")
+                .is_some_and(|key| SORTED.contains(&key))
+        },
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path.starts_with("tool.coverage.paths."),
+        order: Order::AnyAfterFirst,
+        ..Normalization::NONE
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::coverage::fix;
+    use crate::helpers::table::Tables;
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_coverage(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::sections(
+        indoc ! {r#"
+    [tool.coverage.report]
+    show_missing = true
+    fail_under = 100
+    exclude_also = ["if TYPE_CHECKING:", "def __repr__"]
+
+    [tool.coverage.html]
+    title = "report"
+    directory = "htmlcov"
+
+    [tool.coverage.paths]
+    source = ["src", "*/site-packages", "*\\src"]
+
+    [tool.coverage.run]
+    # measure branches too
+    branch = true
+    source = ["pkg", "tests"]
+    omit = ["*/b.py", "*/a.py"]
+    plugins = ["covdefaults"]
+    "#},
+        indoc ! {r#"
+    [tool.coverage]
+    run.source = [
+      "pkg",
+      "tests",
+    ]
+    run.omit = [
+      "*/a.py",
+      "*/b.py",
+    ]
+    # measure branches too
+    run.branch = true
+    run.plugins = [
+      "covdefaults",
+    ]
+    paths.source = [
+      "src",
+      "*/site-packages",
+      "*\\src",
+    ]
+    report.fail_under = 100
+    report.show_missing = true
+    report.exclude_also = [
+      "def __repr__",
+      "if TYPE_CHECKING:",
+    ]
+    html.directory = "htmlcov"
+    html.title = "report"
+    "#},
+    )]
+    #[case::canonical_path_stays_first(
+        indoc ! {r#"
+    [tool.coverage]
+    paths.other = ["z/", "b/", "a/"]
+    "#},
+        indoc ! {r#"
+    [tool.coverage]
+    paths.other = [
+      "z/",
+      "a/",
+      "b/",
+    ]
+    "#},
+    )]
+    fn test_format_coverage(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+}
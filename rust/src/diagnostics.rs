@@ -0,0 +1,468 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use pep440_rs::{Version, VersionSpecifiers};
+use pep508_rs::Requirement;
+use pyo3::pyclass;
+use serde_json::{json, Value};
+use taplo::dom::Node;
+use taplo::parser::parse;
+use taplo::rowan::{TextRange, TextSize};
+
+use crate::helpers::dom::{lookup, range_of, strings};
+use crate::poetry::migration_problems;
+use crate::setuptools::dynamic_problems;
+use crate::validate::{line_column, validate};
+
+/// The checks run, by code and a short description of what they report.
+pub const RULES: &[(&str, &str)] = &[
+    ("PF001", "the document is not valid TOML"),
+    ("PF002", "a key or table is defined more than once"),
+    ("PF101", "a requirement is not valid PEP 508"),
+    ("PF102", "requires-python is not a valid PEP 440 version specifier"),
+    ("PF103", "requires-python excludes every supported Python version"),
+    ("PF104", "a classifier is not of the form 'Category :: Value'"),
+    (
+        "PF105",
+        "a Python version classifier is excluded by requires-python and will be removed",
+    ),
+    ("PF106", "a tool.poetry setting cannot be migrated to the project table"),
+    (
+        "PF107",
+        "a tool.setuptools.dynamic entry is not listed in project.dynamic",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// A problem found in the document, located by its byte range within the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub range: TextRange,
+}
+
+/// A problem found in the document, as exposed to Python (positions are one based).
+#[pyclass(name = "Diagnostic", frozen, get_all)]
+pub struct PyDiagnostic {
+    code: String,
+    severity: String,
+    message: String,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+impl PyDiagnostic {
+    pub fn new(diagnostic: &Diagnostic, content: &str) -> Self {
+        let ((line, column), (end_line, end_column)) = span(diagnostic, content);
+        Self {
+            code: String::from(diagnostic.code),
+            severity: diagnostic.severity.to_string(),
+            message: diagnostic.message.clone(),
+            start: diagnostic.range.start().into(),
+            end: diagnostic.range.end().into(),
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// Collect the problems of the document: invalid syntax, conflicting definitions, and the values the fixers cannot
+/// make sense of (with `migrate_poetry` also the poetry settings left in place by the migration).
+pub fn diagnose(
+    content: &str,
+    min_supported_python: (u8, u8),
+    max_supported_python: (u8, u8),
+    migrate_poetry: bool,
+) -> Vec<Diagnostic> {
+    let parsed = parse(content);
+    let mut diagnostics = parsed
+        .errors
+        .iter()
+        .map(|e| Diagnostic {
+            code: "PF001",
+            severity: Severity::Error,
+            message: e.message.clone(),
+            range: e.range,
+        })
+        .collect::<Vec<Diagnostic>>();
+    diagnostics.extend(validate(content).into_iter().map(|p| Diagnostic {
+        code: "PF002",
+        severity: Severity::Error,
+        message: p.message,
+        range: p.range,
+    }));
+    let root = parsed.into_dom();
+    for path in [&["build-system", "requires"][..], &["project", "dependencies"]] {
+        check_requirements(lookup(&root, path).as_ref(), &mut diagnostics);
+    }
+    if let Some(Node::Table(extras)) = lookup(&root, &["project", "optional-dependencies"]) {
+        for (_, requirements) in extras.entries().read().iter() {
+            check_requirements(Some(requirements), &mut diagnostics);
+        }
+    }
+    let supported = (min_supported_python.1..=max_supported_python.1).collect::<Vec<u8>>();
+    let allowed = check_requires_python(
+        lookup(&root, &["project", "requires-python"]).as_ref(),
+        &supported,
+        &mut diagnostics,
+    );
+    check_classifiers(
+        lookup(&root, &["project", "classifiers"]).as_ref(),
+        &allowed,
+        &mut diagnostics,
+    );
+    diagnostics.extend(dynamic_problems(&root).into_iter().map(|(message, node)| Diagnostic {
+        code: "PF107",
+        severity: Severity::Warning,
+        message,
+        range: range_of(&node),
+    }));
+    if migrate_poetry {
+        diagnostics.extend(migration_problems(&root).into_iter().map(|(message, node)| Diagnostic {
+            code: "PF106",
+            severity: Severity::Warning,
+            message,
+            range: range_of(&node),
+        }));
+    }
+    diagnostics.sort_by_key(|d| (d.range.start(), d.code));
+    diagnostics
+}
+
+fn check_requirements(node: Option<&Node>, diagnostics: &mut Vec<Diagnostic>) {
+    for (value, item) in strings(node) {
+        if let Err(err) = Requirement::from_str(&value) {
+            diagnostics.push(Diagnostic {
+                code: "PF101",
+                severity: Severity::Error,
+                message: format!("invalid requirement {value:?}: {}", err.message),
+                range: range_of(&item),
+            });
+        }
+    }
+}
+
+/// Check requires-python, returns the supported Python 3 minor versions it allows.
+fn check_requires_python(node: Option<&Node>, supported: &[u8], diagnostics: &mut Vec<Diagnostic>) -> Vec<u8> {
+    let Some(value) = node.and_then(Node::as_str) else {
+        return supported.to_vec();
+    };
+    let range = range_of(node.unwrap());
+    match VersionSpecifiers::from_str(value.value()) {
+        Err(err) => {
+            let reason = err.to_string();
+            diagnostics.push(Diagnostic {
+                code: "PF102",
+                severity: Severity::Error,
+                message: format!(
+                    "invalid requires-python {:?}: {}",
+                    value.value(),
+                    reason.lines().next().unwrap_or_default().trim_end_matches(':')
+                ),
+                range,
+            });
+            supported.to_vec()
+        }
+        Ok(specifiers) => {
+            let allowed = supported
+                .iter()
+                .copied()
+                .filter(|minor| specifiers.contains(&Version::new([3, u64::from(*minor)])))
+                .collect::<Vec<u8>>();
+            if allowed.is_empty() {
+                diagnostics.push(Diagnostic {
+                    code: "PF103",
+                    severity: Severity::Warning,
+                    message: format!(
+                        "requires-python {:?} excludes every supported Python version (3.{} to 3.{})",
+                        value.value(),
+                        supported.first().unwrap_or(&0),
+                        supported.last().unwrap_or(&0)
+                    ),
+                    range,
+                });
+            }
+            allowed
+        }
+    }
+}
+
+fn check_classifiers(node: Option<&Node>, allowed: &[u8], diagnostics: &mut Vec<Diagnostic>) {
+    for (value, item) in strings(node) {
+        let parts = value.split("::").map(str::trim).collect::<Vec<&str>>();
+        if parts.len() < 2 || parts.iter().any(|p| p.is_empty()) {
+            diagnostics.push(Diagnostic {
+                code: "PF104",
+                severity: Severity::Warning,
+                message: format!("classifier {value:?} is not of the form 'Category :: Value'"),
+                range: range_of(&item),
+            });
+        } else if let Some(minor) = value
+            .strip_prefix("Programming Language :: Python :: 3.")
+            .and_then(|m| m.parse::<u8>().ok())
+        {
+            if !allowed.contains(&minor) {
+                diagnostics.push(Diagnostic {
+                    code: "PF105",
+                    severity: Severity::Warning,
+                    message: format!("classifier {value:?} is excluded by requires-python and will be removed"),
+                    range: range_of(&item),
+                });
+            }
+        }
+    }
+}
+
+/// One based line and column of the start and end of the diagnostic.
+fn span(diagnostic: &Diagnostic, content: &str) -> ((usize, usize), (usize, usize)) {
+    (
+        line_column(content, diagnostic.range.start()),
+        line_column(content, diagnostic.range.end()),
+    )
+}
+
+/// The diagnostics as a JSON array.
+pub fn to_json(diagnostics: &[Diagnostic], content: &str) -> String {
+    let entries = diagnostics
+        .iter()
+        .map(|d| {
+            let ((line, column), (end_line, end_column)) = span(d, content);
+            json!({
+                "code": d.code,
+                "severity": d.severity.to_string(),
+                "message": d.message,
+                "start": usize::from(d.range.start()),
+                "end": usize::from(d.range.end()),
+                "line": line,
+                "column": column,
+                "end_line": end_line,
+                "end_column": end_column,
+            })
+        })
+        .collect::<Vec<Value>>();
+    serde_json::to_string_pretty(&entries).unwrap()
+}
+
+/// The one based line and column of the offset, the column counted in UTF-16 code units as SARIF does by default.
+fn utf16_position(content: &str, offset: TextSize) -> (usize, usize) {
+    let before = &content[..usize::from(offset).min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |at| at + 1);
+    (
+        line_column(content, offset).0,
+        before[line_start..].encode_utf16().count() + 1,
+    )
+}
+
+/// The diagnostics as a SARIF 2.1.0 log, reported against the file at `path`.
+pub fn to_sarif(diagnostics: &[Diagnostic], content: &str, path: &str) -> String {
+    let rules = RULES
+        .iter()
+        .map(|(code, description)| json!({"id": code, "shortDescription": {"text": description}}))
+        .collect::<Vec<Value>>();
+    let results = diagnostics
+        .iter()
+        .map(|d| {
+            let (line, column) = utf16_position(content, d.range.start());
+            let (end_line, end_column) = utf16_position(content, d.range.end());
+            json!({
+                "ruleId": d.code,
+                "level": d.severity.to_string(),
+                "message": {"text": d.message},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": path},
+                        "region": {
+                            "startLine": line,
+                            "startColumn": column,
+                            "endLine": end_line,
+                            "endColumn": end_column,
+                        },
+                    },
+                }],
+            })
+        })
+        .collect::<Vec<Value>>();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "pyproject-fmt",
+                    "informationUri": "https://github.com/tox-dev/pyproject-fmt",
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+
+    use crate::diagnostics::{diagnose, to_json, to_sarif};
+
+    #[rstest]
+    #[case::clean(
+        indoc ! {r#"
+    [project]
+    requires-python = ">=3.9"
+    dependencies = ["a>=1"]
+    classifiers = ["Programming Language :: Python :: 3.9"]
+    "#},
+        &[]
+    )]
+    #[case::syntax(
+        indoc ! {r"
+    [project
+    "},
+        &["1:9 PF001 error: expected \"]\""]
+    )]
+    #[case::duplicate_table(
+        indoc ! {r"
+    [project]
+    [project]
+    "},
+        &["2:2 PF002 error: table [project] defined more than once, first at line 1"]
+    )]
+    #[case::requirements(
+        indoc ! {r#"
+    [build-system]
+    requires = ["hatchling>="]
+    [project]
+    dependencies = ["a", "b c"]
+    optional-dependencies.test = ["d;;"]
+    "#},
+        &[
+            "2:13 PF101 error: invalid requirement \"hatchling>=\": Unexpected end of version specifier, expected version",
+            "4:22 PF101 error: invalid requirement \"b c\": Expected one of `@`, `(`, `<`, `=`, `>`, `~`, `!`, `;`, found `c`",
+            "5:31 PF101 error: invalid requirement \"d;;\": Expected a valid marker name, found ';'",
+        ]
+    )]
+    #[case::requires_python(
+        indoc ! {r#"
+    [project]
+    requires-python = ">=3.x"
+    "#},
+        &["2:19 PF102 error: invalid requires-python \">=3.x\": Failed to parse version: after parsing 3, found \".x\" after it, which is not part of a valid version"]
+    )]
+    #[case::requires_python_unsupported(
+        indoc ! {r#"
+    [project]
+    requires-python = ">=4"
+    "#},
+        &["2:19 PF103 warning: requires-python \">=4\" excludes every supported Python version (3.9 to 3.13)"]
+    )]
+    #[case::classifiers(
+        indoc ! {r#"
+    [project]
+    requires-python = ">=3.10"
+    classifiers = ["Private", "Programming Language :: Python :: 3.9", "Programming Language :: Python :: 3.10"]
+    "#},
+        &[
+            "3:16 PF104 warning: classifier \"Private\" is not of the form 'Category :: Value'",
+            "3:27 PF105 warning: classifier \"Programming Language :: Python :: 3.9\" is excluded by requires-python and will be removed",
+        ]
+    )]
+    #[case::setuptools_dynamic(
+        indoc ! {r#"
+    [project]
+    dynamic = ["version"]
+    [tool.setuptools.dynamic]
+    readme = { file = "README.md" }
+    "#},
+        &["4:10 PF107 warning: tool.setuptools.dynamic.readme is set but readme is not listed in project.dynamic"]
+    )]
+    fn test_diagnose(#[case] start: &str, #[case] expected: &[&str]) {
+        let got = diagnose(start, (3, 9), (3, 13), false)
+            .iter()
+            .map(|d| {
+                let (line, column) = crate::validate::line_column(start, d.range.start());
+                format!("{line}:{column} {} {}: {}", d.code, d.severity, d.message)
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(got, expected);
+    }
+
+    #[rstest]
+    fn test_to_json() {
+        let start = "[project]\nrequires-python = \">=4\"\n";
+        let got = to_json(&diagnose(start, (3, 9), (3, 13), false), start);
+        let expected = indoc! {r#"
+        [
+          {
+            "code": "PF103",
+            "column": 19,
+            "end": 33,
+            "end_column": 24,
+            "end_line": 2,
+            "line": 2,
+            "message": "requires-python \">=4\" excludes every supported Python version (3.9 to 3.13)",
+            "severity": "warning",
+            "start": 28
+          }
+        ]"#};
+        assert_eq!(got, expected);
+    }
+
+    #[rstest]
+    fn test_to_sarif() {
+        let start = "[project]\nrequires-python = \">=4\"\n";
+        let got: serde_json::Value = serde_json::from_str(&to_sarif(
+            &diagnose(start, (3, 9), (3, 13), false),
+            start,
+            "pyproject.toml",
+        ))
+        .unwrap();
+        assert_eq!(got["version"], "2.1.0");
+        let result = &got["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "PF103");
+        assert_eq!(result["level"], "warning");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "pyproject.toml");
+        assert_eq!(location["region"]["startLine"], 2);
+        assert_eq!(location["region"]["startColumn"], 19);
+        assert_eq!(location["region"]["endColumn"], 24);
+    }
+
+    #[rstest]
+    fn test_to_sarif_non_ascii() {
+        let start = "[project]\nclassifiers = [\"Privé\"]\n";
+        let got: serde_json::Value = serde_json::from_str(&to_sarif(
+            &diagnose(start, (3, 9), (3, 13), false),
+            start,
+            "pyproject.toml",
+        ))
+        .unwrap();
+        let region = &got["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startColumn"], 16);
+        // the column counts UTF-16 code units, é is a single one while taking two bytes
+        assert_eq!(region["endColumn"], 23);
+    }
+}
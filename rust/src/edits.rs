@@ -0,0 +1,196 @@
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use taplo::parser::parse;
+use taplo::syntax::SyntaxElement;
+
+use crate::helpers::table::Tables;
+
+/// Replace the bytes of `range` within the original text with `new_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
+/// The smallest set of non-overlapping edits turning `old` into `new`, with positions shifted by `offset` (for when
+/// `old` is part of a larger text). The texts are compared line by line first, so moved entries show as a removal and
+/// an insertion, then the changed lines by their TOML tokens, so an edit never splits a token.
+pub fn diff(old: &str, new: &str, offset: usize) -> Vec<TextEdit> {
+    let (old_lines, new_lines) = (lines(old), lines(new));
+    let length = |parts: &[&str]| parts.iter().map(|p| p.len()).sum::<usize>();
+    let mut edits = Vec::<TextEdit>::new();
+    for (old_range, new_range) in changes(&old_lines, &new_lines) {
+        let old_start = length(&old_lines[..old_range.start]);
+        let old_text = &old[old_start..old_start + length(&old_lines[old_range.clone()])];
+        let new_start = length(&new_lines[..new_range.start]);
+        let new_text = &new[new_start..new_start + length(&new_lines[new_range.clone()])];
+        if old_range.is_empty() || new_range.is_empty() {
+            let start = offset + old_start;
+            push_edit(&mut edits, start..start + old_text.len(), String::from(new_text));
+            continue;
+        }
+        let (old_tokens, new_tokens) = (tokens(old_text), tokens(new_text));
+        for (old_range, new_range) in changes(&old_tokens, &new_tokens) {
+            let start = offset + old_start + length(&old_tokens[..old_range.start]);
+            let end = start + length(&old_tokens[old_range]);
+            push_edit(&mut edits, start..end, new_tokens[new_range].concat());
+        }
+    }
+    edits
+}
+
+/// The ranges of `old` and `new` that differ, in order. The positions follow from the lengths of the operations, as
+/// the ones reported for an insertion right after an unchanged run may lag behind it.
+fn changes(old: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let (mut old_at, mut new_at, mut changes) = (0, 0, Vec::new());
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        let old_range = old_at..old_at + op.old_range().len();
+        let new_range = new_at..new_at + op.new_range().len();
+        (old_at, new_at) = (old_range.end, new_range.end);
+        if !matches!(op, DiffOp::Equal { .. }) {
+            changes.push((old_range, new_range));
+        }
+    }
+    changes
+}
+
+/// The text with the edits applied, these are sorted and do not overlap.
+pub fn apply(text: &str, edits: &[TextEdit]) -> String {
+    let (mut result, mut at) = (String::new(), 0);
+    for edit in edits {
+        result.push_str(&text[at..edit.range.start]);
+        result.push_str(&edit.new_text);
+        at = edit.range.end;
+    }
+    result.push_str(&text[at..]);
+    result
+}
+
+/// Add the edit, joining it with the previous one when they touch.
+fn push_edit(edits: &mut Vec<TextEdit>, range: Range<usize>, new_text: String) {
+    match edits.last_mut() {
+        Some(last) if last.range.end == range.start => {
+            last.range.end = range.end;
+            last.new_text.push_str(&new_text);
+        }
+        _ => edits.push(TextEdit { range, new_text }),
+    }
+}
+
+/// The text of each token of the TOML document, in order (these add up to the whole text).
+fn tokens(text: &str) -> Vec<&str> {
+    parse(text)
+        .into_syntax()
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .map(|t| &text[t.text_range()])
+        .collect()
+}
+
+/// The text split into lines at the tokens ending with a newline, so a line never splits a token.
+fn lines(text: &str) -> Vec<&str> {
+    let (mut lines, mut start, mut at) = (Vec::new(), 0, 0);
+    for token in tokens(text) {
+        at += token.len();
+        if token.ends_with('\n') {
+            lines.push(&text[start..at]);
+            start = at;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// The part of the document made of the tables intersecting `range` (an empty range selects the table under that
+/// position), from the start of the first to the end of the last one, or `None` if no table is selected.
+pub fn selected_tables(content: &str, range: &Range<usize>) -> Option<Range<usize>> {
+    let root_ast = parse(content).into_syntax();
+    let tables = Tables::from_ast(&root_ast, false);
+    let (start, end) = (range.start, range.end.max(range.start + 1));
+    tables
+        .table_set
+        .iter()
+        .filter_map(|table| {
+            let table = table.borrow();
+            let first = usize::from(table.first()?.text_range().start());
+            let last = usize::from(table.last()?.text_range().end());
+            (first < end && start < last).then_some(first..last)
+        })
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+/// Byte range of the lines `first` to `last` (one based, inclusive), including the newline ending the last one.
+pub fn line_range(content: &str, first: usize, last: usize) -> Range<usize> {
+    let start_of_line = |line: usize| match line {
+        0 | 1 => 0,
+        _ => content
+            .match_indices('\n')
+            .nth(line - 2)
+            .map_or(content.len(), |(at, _)| at + 1),
+    };
+    start_of_line(first)..start_of_line(last + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+
+    use crate::edits::{diff, line_range, selected_tables, TextEdit};
+
+    #[rstest]
+    #[case::same("a = 1\n", "a = 1\n", vec![])]
+    #[case::whitespace(
+        "a = 1\nb=2\nc = 3\n",
+        "a = 1\nb = 2\nc = 3\n",
+        vec![
+            TextEdit { range: 7..7, new_text: String::from(" ") },
+            TextEdit { range: 8..8, new_text: String::from(" ") },
+        ]
+    )]
+    #[case::token_replaced(
+        "a = [\"b>=1.0\", \"c\"]\n",
+        "a = [\"b>=1\", \"c\"]\n",
+        vec![TextEdit { range: 5..13, new_text: String::from("\"b>=1\"") }]
+    )]
+    #[case::removed_and_added(
+        "a = 1\nb = 2\nc = 3\n",
+        "b = 2\nc = 3\nd = 4\n",
+        vec![
+            TextEdit { range: 0..6, new_text: String::new() },
+            TextEdit { range: 18..18, new_text: String::from("d = 4\n") },
+        ]
+    )]
+    fn test_diff(#[case] old: &str, #[case] new: &str, #[case] expected: Vec<TextEdit>) {
+        assert_eq!(diff(old, new, 0), expected);
+    }
+
+    #[rstest]
+    #[case::cursor_in_second(9..9, Some(6..16))]
+    #[case::spanning_two(2..9, Some(0..16))]
+    #[case::root_entries(0..0, Some(0..6))]
+    #[case::past_end(40..41, None)]
+    fn test_selected_tables(#[case] range: std::ops::Range<usize>, #[case] expected: Option<std::ops::Range<usize>>) {
+        let content = indoc! {"
+        a = 1
+        [b]
+        c = 2
+        [d]
+        e = 3
+        "};
+        assert_eq!(selected_tables(content, &range), expected);
+    }
+
+    #[rstest]
+    #[case::first(1, 1, 0..6)]
+    #[case::middle(2, 3, 6..16)]
+    #[case::last(5, 5, 20..26)]
+    #[case::past_end(5, 9, 20..26)]
+    fn test_line_range(#[case] first: usize, #[case] last: usize, #[case] expected: std::ops::Range<usize>) {
+        let content = "a = 1\n[b]\nc = 2\n[d]\ne = 3\n";
+        assert_eq!(line_range(content, first, last), expected);
+    }
+}
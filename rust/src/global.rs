@@ -1,70 +1,152 @@
+use std::collections::VecDeque;
+use std::iter::once;
+
 use taplo::rowan::SyntaxNode;
 use taplo::syntax::Lang;
 
-use crate::helpers::table::Tables;
+use crate::helpers::table::{collapse_tables_where, expand_sub_tables, split_name, Tables};
+
+/// Apply the sub-table layout policy: `short` folds the sub-tables of a table into dotted keys, `long` expands dotted
+/// keys into sub-table headers, while `keep` leaves the table as written. The most specific entry from
+/// `expand_tables`/`collapse_tables` wins (e.g. `tool.ruff.lint` over `tool.ruff`), with expansion winning a tie,
+/// falling back to `table_format` otherwise.
+pub fn apply_table_format(
+    tables: &mut Tables,
+    table_format: &str,
+    expand_tables: &[String],
+    collapse_tables: &[String],
+) {
+    let format_of = |name: &str| table_format_of(name, table_format, expand_tables, collapse_tables);
+
+    let mut queue = tables
+        .header_to_pos
+        .iter()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(k, v)| (split_name(k).len(), v[0], k.clone()))
+        .collect::<Vec<(usize, usize, String)>>();
+    queue.sort();
+    let mut queue = queue.into_iter().map(|(_, _, k)| k).collect::<VecDeque<String>>();
+    // parents first, so that the sub-tables created by the expansion get expanded in turn
+    while let Some(name) = queue.pop_front() {
+        if format_of(&name) == "long" {
+            queue.extend(expand_sub_tables(tables, &name));
+        }
+    }
+
+    collapse_tables_where(tables, |name| {
+        let parts = split_name(name);
+        let root_depth = if parts[0] == "tool" { 2 } else { 1 };
+        parts.len() > root_depth && format_of(&parts[..parts.len() - 1].join(".")) == "short"
+    });
+}
+
+fn table_format_of<'a>(
+    name: &str,
+    table_format: &'a str,
+    expand_tables: &[String],
+    collapse_tables: &[String],
+) -> &'a str {
+    let specificity = |patterns: &[String]| {
+        patterns
+            .iter()
+            .filter(|p| name == p.as_str() || name.starts_with(format!("{p}.").as_str()))
+            .map(String::len)
+            .max()
+    };
+    match (specificity(expand_tables), specificity(collapse_tables)) {
+        (Some(expand), Some(collapse)) if expand >= collapse => "long",
+        (Some(_) | None, Some(_)) => "short",
+        (Some(_), None) => "long",
+        (None, None) => table_format,
+    }
+}
+
+const TABLE_ORDER: &[&str] = &[
+    "",
+    "build-system",
+    "project",
+    // Build backends
+    "tool.poetry",
+    "tool.poetry-dynamic-versioning",
+    "tool.pdm",
+    "tool.uv",
+    "tool.setuptools",
+    "tool.distutils",
+    "tool.setuptools_scm",
+    "tool.hatch",
+    "tool.flit",
+    "tool.scikit-build",
+    "tool.meson-python",
+    "tool.maturin",
+    "tool.whey",
+    "tool.py-build-cmake",
+    "tool.sphinx-theme-builder",
+    // Builders
+    "tool.cibuildwheel",
+    // Formatters and linters
+    "tool.autopep8",
+    "tool.black",
+    "tool.ruff",
+    "tool.isort",
+    "tool.flake8",
+    "tool.pycln",
+    "tool.nbqa",
+    "tool.pylint",
+    "tool.repo-review",
+    "tool.codespell",
+    "tool.docformatter",
+    "tool.pydoclint",
+    "tool.tomlsort",
+    "tool.check-manifest",
+    "tool.check-sdist",
+    "tool.check-wheel-contents",
+    "tool.deptry",
+    "tool.pyproject-fmt",
+    // Testing
+    "tool.pytest",
+    "tool.pytest_env",
+    "tool.pytest-enabler",
+    "tool.coverage",
+    // Runners
+    "tool.doit",
+    "tool.spin",
+    "tool.tox",
+    // Releasers/bumpers
+    "tool.bumpversion",
+    "tool.jupyter-releaser",
+    "tool.tbump",
+    "tool.towncrier",
+    "tool.vendoring",
+    // Type checking
+    "tool.mypy",
+    "tool.pyright",
+];
 
-pub fn reorder_tables(root_ast: &SyntaxNode<Lang>, tables: &Tables) {
+/// Order tables by `TABLE_ORDER`, extended with the user supplied `table_order` (may contain glob patterns such as
+/// `tool.my-company-*`): `prepend` ranks them before the default order, `append` after it, and `override` replaces
+/// it. The top level table always stays first. Groups of tables (e.g. `[tool.ruff]` with its sub-tables) are
+/// separated by `blank_lines_between_groups` empty lines, tables within a group by `blank_lines_between_sub_tables`.
+pub fn reorder_tables(
+    root_ast: &SyntaxNode<Lang>,
+    tables: &Tables,
+    table_order: &[String],
+    table_order_mode: &str,
+    sort_unknown_tools: bool,
+    blank_lines_between_groups: usize,
+    blank_lines_between_sub_tables: Option<usize>,
+) {
+    let user_order = table_order.iter().map(String::as_str);
+    let order: Vec<&str> = match table_order_mode {
+        "override" => once("").chain(user_order).collect(),
+        "append" => TABLE_ORDER.iter().copied().chain(user_order).collect(),
+        _ => once("").chain(user_order).chain(TABLE_ORDER.iter().copied()).collect(),
+    };
     tables.reorder(
         root_ast,
-        &[
-            "",
-            "build-system",
-            "project",
-            // Build backends
-            "tool.poetry",
-            "tool.poetry-dynamic-versioning",
-            "tool.pdm",
-            "tool.setuptools",
-            "tool.distutils",
-            "tool.setuptools_scm",
-            "tool.hatch",
-            "tool.flit",
-            "tool.scikit-build",
-            "tool.meson-python",
-            "tool.maturin",
-            "tool.whey",
-            "tool.py-build-cmake",
-            "tool.sphinx-theme-builder",
-            // Builders
-            "tool.cibuildwheel",
-            // Formatters and linters
-            "tool.autopep8",
-            "tool.black",
-            "tool.ruff",
-            "tool.isort",
-            "tool.flake8",
-            "tool.pycln",
-            "tool.nbqa",
-            "tool.pylint",
-            "tool.repo-review",
-            "tool.codespell",
-            "tool.docformatter",
-            "tool.pydoclint",
-            "tool.tomlsort",
-            "tool.check-manifest",
-            "tool.check-sdist",
-            "tool.check-wheel-contents",
-            "tool.deptry",
-            "tool.pyproject-fmt",
-            // Testing
-            "tool.pytest",
-            "tool.pytest_env",
-            "tool.pytest-enabler",
-            "tool.coverage",
-            // Runners
-            "tool.doit",
-            "tool.spin",
-            "tool.tox",
-            // Releasers/bumpers
-            "tool.bumpversion",
-            "tool.jupyter-releaser",
-            "tool.tbump",
-            "tool.towncrier",
-            "tool.vendoring",
-            // Type checking
-            "tool.mypy",
-            "tool.pyright",
-        ],
+        &order,
+        sort_unknown_tools,
+        blank_lines_between_groups,
+        blank_lines_between_sub_tables,
     );
 }
 
@@ -75,7 +157,7 @@ mod tests {
     use taplo::formatter::{format_syntax, Options};
     use taplo::parser::parse;
 
-    use crate::global::reorder_tables;
+    use crate::global::{apply_table_format, reorder_tables};
     use crate::helpers::table::Tables;
 
     #[rstest]
@@ -161,8 +243,8 @@ mod tests {
     )]
     fn test_reorder_table(#[case] start: &str, #[case] expected: &str) {
         let root_ast = parse(start).into_syntax().clone_for_update();
-        let tables = Tables::from_ast(&root_ast);
-        reorder_tables(&root_ast, &tables);
+        let tables = Tables::from_ast(&root_ast, false);
+        reorder_tables(&root_ast, &tables, &[], "prepend", false, 1, None);
         let opt = Options {
             column_width: 1,
             ..Options::default()
@@ -170,4 +252,390 @@ mod tests {
         let got = format_syntax(root_ast, opt);
         assert_eq!(got, expected);
     }
+
+    #[rstest]
+    #[case::prepend(
+        indoc ! {r"
+    a = 1
+    [tool.zeta]
+    b = 2
+    [project]
+    c = 3
+    [tool.my-company-lint]
+    d = 4
+    "},
+        indoc ! {r"
+    a = 1
+
+    [tool.my-company-lint]
+    d = 4
+
+    [tool.zeta]
+    b = 2
+
+    [project]
+    c = 3
+    "},
+        &["tool.my-company-*", "tool.zeta"],
+        "prepend",
+        false,
+    )]
+    #[case::append(
+        indoc ! {r"
+    [tool.zeta]
+    b = 2
+    [tool.alpha]
+    e = 5
+    [tool.mypy]
+    c = 3
+    "},
+        indoc ! {r"
+    [tool.mypy]
+    c = 3
+
+    [tool.zeta]
+    b = 2
+
+    [tool.alpha]
+    e = 5
+    "},
+        &["tool.zeta"],
+        "append",
+        false,
+    )]
+    #[case::override_order(
+        indoc ! {r"
+    [tool.ruff]
+    b = 2
+    [tool.mypy]
+    c = 3
+    [project]
+    d = 4
+    "},
+        indoc ! {r"
+    [tool.mypy]
+    c = 3
+
+    [project]
+    d = 4
+
+    [tool.ruff]
+    b = 2
+    "},
+        &["tool.mypy", "project"],
+        "override",
+        false,
+    )]
+    #[case::sort_unknown_tools(
+        indoc ! {r"
+    [demo]
+    a = 1
+    [tool.zeta]
+    b = 2
+    [tool.alpha.sub]
+    e = 5
+    [tool.mypy]
+    c = 3
+    [tool.alpha]
+    f = 6
+    "},
+        indoc ! {r"
+    [tool.mypy]
+    c = 3
+
+    [tool.alpha]
+    f = 6
+    [tool.alpha.sub]
+    e = 5
+
+    [tool.zeta]
+    b = 2
+
+    [demo]
+    a = 1
+    "},
+        &[],
+        "prepend",
+        true,
+    )]
+    fn test_reorder_table_custom_order(
+        #[case] start: &str,
+        #[case] expected: &str,
+        #[case] table_order: &[&str],
+        #[case] table_order_mode: &str,
+        #[case] sort_unknown_tools: bool,
+    ) {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let tables = Tables::from_ast(&root_ast, false);
+        let table_order = table_order.iter().map(|e| String::from(*e)).collect::<Vec<String>>();
+        reorder_tables(
+            &root_ast,
+            &tables,
+            &table_order,
+            table_order_mode,
+            sort_unknown_tools,
+            1,
+            None,
+        );
+        let got = format_syntax(root_ast, Options::default());
+        assert_eq!(got, expected);
+    }
+
+    #[rstest]
+    #[case::comments_stay_with_header(
+        indoc ! {r#"
+    [tool.ruff]
+    a = 1
+    # about the project
+    [project]
+    name = "a"
+    # the build
+    # backend
+    [build-system]
+    b = 2
+    "#},
+        indoc ! {r#"
+    # the build
+    # backend
+    [build-system]
+    b = 2
+
+    # about the project
+    [project]
+    name = "a"
+
+    [tool.ruff]
+    a = 1
+    "#},
+        false,
+        1,
+        None,
+    )]
+    #[case::banner_stays_with_table_below(
+        indoc ! {r#"
+    [tool.ruff]
+    a = 1
+
+    # ----- project -----
+
+    [project]
+    name = "a"
+    "#},
+        indoc ! {r#"
+    # ----- project -----
+
+    [project]
+    name = "a"
+
+    [tool.ruff]
+    a = 1
+    "#},
+        true,
+        1,
+        None,
+    )]
+    #[case::banner_stays_with_table_above(
+        indoc ! {r#"
+    [tool.ruff]
+    a = 1
+
+    # ----- project -----
+
+    [project]
+    name = "a"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "a"
+
+    [tool.ruff]
+    a = 1
+
+    # ----- project -----
+    "#},
+        false,
+        1,
+        None,
+    )]
+    #[case::no_blank_lines_within_group(
+        indoc ! {r#"
+    [tool.ruff]
+    a = 1
+
+    [tool.ruff.lint]
+    b = 2
+    [project]
+    name = "a"
+    [project.urls]
+    c = "d"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "a"
+    [project.urls]
+    c = "d"
+
+
+    [tool.ruff]
+    a = 1
+    [tool.ruff.lint]
+    b = 2
+    "#},
+        false,
+        2,
+        Some(0),
+    )]
+    fn test_reorder_table_spacing(
+        #[case] start: &str,
+        #[case] expected: &str,
+        #[case] keep_banner_comments: bool,
+        #[case] blank_lines_between_groups: usize,
+        #[case] blank_lines_between_sub_tables: Option<usize>,
+    ) {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let tables = Tables::from_ast(&root_ast, keep_banner_comments);
+        reorder_tables(
+            &root_ast,
+            &tables,
+            &[],
+            "prepend",
+            false,
+            blank_lines_between_groups,
+            blank_lines_between_sub_tables,
+        );
+        let options = Options {
+            allowed_blank_lines: 2,
+            ..Options::default()
+        };
+        let got = format_syntax(root_ast, options);
+        assert_eq!(got, expected);
+    }
+
+    #[rstest]
+    #[case::expand(
+        indoc ! {r#"
+    [tool.ruff]
+    line-length = 120
+    # the rules
+    lint.select = ["ALL"] # everything
+    lint.isort.known-first-party = ["a"]
+    "#},
+        indoc ! {r#"
+    [tool.ruff]
+    line-length = 120
+    [tool.ruff.lint]
+    # the rules
+    select = ["ALL"] # everything
+    [tool.ruff.lint.isort]
+    known-first-party = ["a"]
+    "#},
+        "keep",
+        &["tool.ruff"],
+        &[],
+    )]
+    #[case::expand_stop_at_collapse(
+        indoc ! {r#"
+    [tool.ruff]
+    lint.select = ["ALL"]
+    lint.isort.known-first-party = ["a"]
+    "#},
+        indoc ! {r#"
+    [tool.ruff]
+    [tool.ruff.lint]
+    select = ["ALL"]
+    isort.known-first-party = ["a"]
+    "#},
+        "long",
+        &[],
+        &["tool.ruff.lint"],
+    )]
+    #[case::collapse(
+        indoc ! {r"
+    [tool.coverage.report]
+    a = 2 # two
+    [tool.coverage.run] # runner
+    a = 3
+    [tool.coverage.run.x]
+    b.c = 4
+    "},
+        indoc ! {r"
+    [tool.coverage]
+    report.a = 2 # two
+    # runner
+    run.a = 3
+    run.x.b.c = 4
+    "},
+        "short",
+        &[],
+        &[],
+    )]
+    #[case::collapse_per_table(
+        indoc ! {r"
+    [tool.coverage.report]
+    a = 2
+    [tool.mypy]
+    strict = true
+    [tool.mypy.x]
+    a = 1
+    "},
+        indoc ! {r"
+    [tool.coverage.report]
+    a = 2
+
+    [tool.mypy]
+    strict = true
+    x.a = 1
+    "},
+        "keep",
+        &[],
+        &["tool.mypy"],
+    )]
+    #[case::array_of_tables(
+        indoc ! {r#"
+    [tool.towncrier]
+    a.b = 1
+    [[tool.towncrier.type]]
+    name = "x"
+    c.d = 1
+    [[tool.towncrier.type]]
+    name = "y"
+    "#},
+        indoc ! {r#"
+    [tool.towncrier]
+    [[tool.towncrier.type]]
+    name = "x"
+    c.d = 1
+    [[tool.towncrier.type]]
+    name = "y"
+    [tool.towncrier.a]
+    b = 1
+    "#},
+        "long",
+        &[],
+        &[],
+    )]
+    fn test_table_format(
+        #[case] start: &str,
+        #[case] expected: &str,
+        #[case] table_format: &str,
+        #[case] expand_tables: &[&str],
+        #[case] collapse_tables: &[&str],
+    ) {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        let to_vec = |v: &[&str]| v.iter().map(|e| String::from(*e)).collect::<Vec<String>>();
+        apply_table_format(
+            &mut tables,
+            table_format,
+            &to_vec(expand_tables),
+            &to_vec(collapse_tables),
+        );
+        reorder_tables(&root_ast, &tables, &[], "prepend", false, 1, None);
+        let opt = Options {
+            column_width: 120,
+            ..Options::default()
+        };
+        let got = format_syntax(root_ast, opt);
+        assert_eq!(got, expected);
+    }
 }
@@ -0,0 +1,230 @@
+use taplo::syntax::SyntaxNode;
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, transform};
+use crate::helpers::pep508::format_requirement_or_keep;
+use crate::helpers::table::{collapse_tables_where, for_entries, reorder_tables, split_name, Tables};
+
+pub fn fix(tables: &mut Tables, keep_full_version: bool) {
+    // each environment is a single table, its sub-tables (e.g. scripts) become dotted keys of it
+    collapse_tables_where(tables, |name| {
+        let parts = split_name(name);
+        parts.len() > 4 && is_env(&parts[..4])
+    });
+    let mut envs = Vec::<String>::new();
+    for (name, positions) in &tables.header_to_pos {
+        let parts = split_name(name);
+        if parts.len() < 2 || parts[0] != "tool" || parts[1] != "hatch" {
+            continue;
+        }
+        let env = is_env(&parts);
+        if env && positions.len() == 1 {
+            envs.push(name.clone());
+        }
+        let build = parts.get(2).is_some_and(|p| p == "build");
+        for position in positions {
+            let table = &tables.table_set[*position].borrow();
+            for_entries(table, &mut |key, entry| {
+                if env {
+                    fix_env(&key, entry, keep_full_version);
+                } else if build || (parts.len() == 2 && key.starts_with("build.")) {
+                    fix_build(&key, entry, keep_full_version);
+                }
+            });
+        }
+    }
+    order_envs(tables, envs);
+}
+
+/// Whether `parts` names an environment table: `tool.hatch.envs.<name>`.
+fn is_env(parts: &[String]) -> bool {
+    matches!(parts, [tool, hatch, envs, _] if tool == "tool" && hatch == "hatch" && envs == "envs")
+}
+
+/// Fix the setting `key` of an environment, which may be prefixed when written as a dotted key of a parent table.
+fn fix_env(key: &str, entry: &SyntaxNode, keep_full_version: bool) {
+    match key.rsplit('.').next().unwrap_or(key) {
+        // may use context formatting (e.g. `{root:uri}`), these are kept as written
+        "dependencies" | "extra-dependencies" => {
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
+        }
+        // the commands of scripts run in order, and matrix values define the order of the generated environments
+        _ => {}
+    }
+}
+
+/// Fix the setting `key` of the build configuration, or one of its targets or hooks.
+fn fix_build(key: &str, entry: &SyntaxNode, keep_full_version: bool) {
+    match key.rsplit('.').next().unwrap_or(key) {
+        "dependencies" => {
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
+        }
+        "packages" | "include" | "exclude" => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        _ => {}
+    }
+}
+
+/// Order the environment tables by name, with the `default` environment (that all others inherit from) first.
+fn order_envs(tables: &mut Tables, mut envs: Vec<String>) {
+    envs.sort_by_cached_key(|name| {
+        let env = split_name(name).pop().unwrap_or_default();
+        let env = env.trim_matches(['"', '\'']).to_lowercase();
+        (env != "default", env)
+    });
+    reorder_tables(tables, &envs);
+}
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| {
+            path.starts_with("tool.hatch.")
+                && matches!(path.rsplit('.').next(), Some("dependencies" | "extra-dependencies"))
+        },
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| {
+            path.starts_with("tool.hatch.build.")
+                && matches!(path.rsplit('.').next(), Some("packages" | "include" | "exclude"))
+        },
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::hatch::fix;
+    use crate::helpers::table::Tables;
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables, false);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_hatch(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::build(
+        indoc ! {r#"
+    [tool.hatch]
+    build.exclude = ["tests", "docs"]
+
+    [tool.hatch.build.targets.wheel]
+    packages = ["src/b", "src/a"]
+
+    [tool.hatch.build.targets.sdist]
+    include = ["src", "LICENSE"]
+
+    [tool.hatch.build.hooks.vcs]
+    version-file = "src/a/_version.py"
+    dependencies = ["hatch-vcs>=0.4.0"]
+    "#},
+        indoc ! {r#"
+    [tool.hatch]
+    build.exclude = [
+      "docs",
+      "tests",
+    ]
+
+    [tool.hatch.build.targets.wheel]
+    packages = [
+      "src/a",
+      "src/b",
+    ]
+
+    [tool.hatch.build.targets.sdist]
+    include = [
+      "LICENSE",
+      "src",
+    ]
+
+    [tool.hatch.build.hooks.vcs]
+    version-file = "src/a/_version.py"
+    dependencies = [
+      "hatch-vcs>=0.4",
+    ]
+    "#},
+    )]
+    #[case::envs(
+        indoc ! {r#"
+    [tool.hatch.envs.test]
+    dependencies = ["pytest>=8.0.0", "a @ {root:uri}/a"]
+
+    [[tool.hatch.envs.test.matrix]]
+    python = ["3.13", "3.12"]
+
+    [[tool.hatch.envs.test.matrix]]
+    python = ["3.9"]
+    feature = ["b", "a"]
+
+    [tool.hatch.envs.test.scripts]
+    run = "pytest {args}"
+
+    [tool.hatch.envs.lint]
+    detached = true
+
+    [tool.hatch.envs.default]
+    extra-dependencies = ["Coverage[toml]>=7.0"]
+    "#},
+        indoc ! {r#"
+    [tool.hatch.envs.default]
+    extra-dependencies = [
+      "coverage[toml]>=7",
+    ]
+
+    [tool.hatch.envs.lint]
+    detached = true
+
+    [tool.hatch.envs.test]
+    dependencies = [
+      "pytest>=8",
+      "a @ {root:uri}/a",
+    ]
+    scripts.run = "pytest {args}"
+
+    [[tool.hatch.envs.test.matrix]]
+    python = [
+      "3.13",
+      "3.12",
+    ]
+
+    [[tool.hatch.envs.test.matrix]]
+    python = [
+      "3.9",
+    ]
+    feature = [
+      "b",
+      "a",
+    ]
+    "#},
+    )]
+    fn test_format_hatch(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+}
@@ -1,8 +1,6 @@
+use lexical_sort::natural_lexical_cmp;
 use std::cell::RefCell;
-use std::collections::HashMap;
-
-use lexical_sort::{natural_lexical_cmp, StringSort};
-use taplo::syntax::SyntaxKind::{ARRAY, COMMA, NEWLINE, STRING, VALUE, WHITESPACE};
+use taplo::syntax::SyntaxKind::{ARRAY, COMMA, NEWLINE, STRING, STRING_LITERAL, VALUE, WHITESPACE};
 use taplo::syntax::{SyntaxElement, SyntaxKind, SyntaxNode};
 
 use crate::helpers::create::{make_comma, make_newline};
@@ -23,6 +21,17 @@ where
     }
 }
 
+/// The string values held by the entry, either the string itself or the strings of the array.
+pub fn string_values(node: &SyntaxNode) -> Vec<String> {
+    node.descendants_with_tokens()
+        .filter(|e| [STRING, STRING_LITERAL].contains(&e.kind()))
+        .map(|e| load_text(e.as_token().unwrap().text(), e.kind()))
+        .collect()
+}
+
+/// Sort the string values of the array. Comments are owned as follows: comment lines above a value travel with it,
+/// as does the comment after it on the same line; comments on the line of the opening bracket or separated from the
+/// first value by a blank line stay at the top (header), and comments after the last value stay at the bottom (footer).
 #[allow(clippy::range_plus_one, clippy::too_many_lines)]
 pub fn sort<F>(node: &SyntaxNode, transform: F)
 where
@@ -38,20 +47,23 @@ where
                 .last()
                 == Some(COMMA);
             let multiline = array_node.children_with_tokens().any(|e| e.kind() == NEWLINE);
-            let mut value_set = Vec::<Vec<SyntaxElement>>::new();
+            let mut value_set = Vec::<(String, Vec<SyntaxElement>)>::new();
             let entry_set = RefCell::new(Vec::<SyntaxElement>::new());
-            let mut key_to_pos = HashMap::<String, usize>::new();
 
             let mut add_to_value_set = |entry: String| {
                 let mut entry_set_borrow = entry_set.borrow_mut();
                 if !entry_set_borrow.is_empty() {
-                    key_to_pos.insert(entry, value_set.len());
-                    value_set.push(entry_set_borrow.clone());
+                    value_set.push((entry, entry_set_borrow.clone()));
                     entry_set_borrow.clear();
                 }
             };
             let mut entries = Vec::<SyntaxElement>::new();
+            let mut header = Vec::<SyntaxElement>::new();
+            let mut footer = Vec::<SyntaxElement>::new();
+            let mut closing = None;
             let mut has_value = false;
+            let mut seen_value = false;
+            let mut on_bracket_line = true;
             let mut previous_is_bracket_open = false;
             let mut entry_value = String::new();
             let mut count = 0;
@@ -61,6 +73,7 @@ where
                 if previous_is_bracket_open {
                     // make sure ends with trailing comma
                     if entry.kind() == NEWLINE || entry.kind() == WHITESPACE {
+                        on_bracket_line &= entry.kind() != NEWLINE;
                         continue;
                     }
                     previous_is_bracket_open = false;
@@ -77,9 +90,9 @@ where
                         if has_value {
                             add_to_value_set(entry_value.clone());
                         } else {
-                            entries.extend(entry_set.borrow_mut().clone());
+                            footer.extend(entry_set.borrow_mut().drain(..));
                         }
-                        entries.push(entry);
+                        closing = Some(entry);
                     }
                     VALUE => {
                         if has_value {
@@ -89,6 +102,7 @@ where
                             add_to_value_set(entry_value.clone());
                         }
                         has_value = true;
+                        seen_value = true;
                         let value_node = entry.as_node().unwrap();
                         let mut found_string = false;
                         for child in value_node.children_with_tokens() {
@@ -107,11 +121,15 @@ where
                         entry_set.borrow_mut().push(make_comma());
                     }
                     NEWLINE => {
+                        let blank_line = entry.as_token().unwrap().text().matches('\n').count() > 1;
                         entry_set.borrow_mut().push(entry);
                         if has_value {
                             add_to_value_set(entry_value.clone());
                             has_value = false;
+                        } else if !seen_value && (on_bracket_line || blank_line) {
+                            header.extend(entry_set.borrow_mut().drain(..));
                         }
+                        on_bracket_line = false;
                     }
                     COMMA => {}
                     _ => {
@@ -120,13 +138,25 @@ where
                 }
             }
 
-            let mut order: Vec<String> = key_to_pos.clone().into_keys().collect();
-            order.string_sort_unstable(natural_lexical_cmp);
-            let end = entries.split_off(if multiline { 2 } else { 1 });
-            for key in order {
-                entries.extend(value_set[key_to_pos[&key]].clone());
+            // the blank line separating the footer belongs to the bottom of the array, not to the last value
+            let mut gap = None;
+            if let Some((_, last)) = value_set.last_mut().filter(|_| !footer.is_empty()) {
+                if last.last().map(SyntaxElement::kind) == Some(NEWLINE) {
+                    gap = last.pop();
+                    last.push(make_newline());
+                }
+            }
+            value_set.sort_by(|(a, _), (b, _)| natural_lexical_cmp(a, b));
+            if let (Some(gap), Some((_, last))) = (gap, value_set.last_mut()) {
+                last.pop();
+                last.push(gap);
+            }
+            entries.extend(header);
+            for (_, value) in value_set {
+                entries.extend(value);
             }
-            entries.extend(end);
+            entries.extend(footer);
+            entries.extend(closing);
             array_node.splice_children(0..count, entries);
             if !has_trailing_comma {
                 if let Some((i, _)) = array_node
@@ -288,6 +318,127 @@ mod tests {
     ] # array comment
     "#}
     )]
+    #[case::header_comment_on_bracket_line(
+        indoc ! {r#"
+    a = [ # header
+      "B",
+      "A",
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      # header
+      "A",
+      "B",
+    ]
+    "#}
+    )]
+    #[case::header_comment_block(
+        indoc ! {r#"
+    a = [
+      # header
+
+      "B",
+      "A",
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      # header
+
+      "A",
+      "B",
+    ]
+    "#}
+    )]
+    #[case::leading_comment(
+        indoc ! {r#"
+    a = [
+      "C",
+      # pinned due to CVE
+      "B",
+      "A",
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      "A",
+      # pinned due to CVE
+      "B",
+      "C",
+    ]
+    "#}
+    )]
+    #[case::inline_comment_last(
+        indoc ! {r#"
+    a = [
+      "B",
+      "A" # last
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      "A", # last
+      "B",
+    ]
+    "#}
+    )]
+    #[case::inline_comment_same_line(
+        indoc ! {r#"
+    a = ["B", "A" # last
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      "A", # last
+      "B",
+    ]
+    "#}
+    )]
+    #[case::duplicate(
+        indoc ! {r#"
+    a = ["B", "A", "B"]
+    "#},
+        indoc ! {r#"
+    a = ["A", "B", "B"]
+    "#}
+    )]
+    #[case::footer_comment(
+        indoc ! {r#"
+    a = [
+      "B",
+      "A",
+      # footer
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      "A",
+      "B",
+      # footer
+    ]
+    "#}
+    )]
+    #[case::footer_comment_after_blank_line(
+        indoc ! {r#"
+    a = [
+      "B",
+      # about A
+      "A",
+
+      # footer
+    ]
+    "#},
+        indoc ! {r#"
+    a = [
+      # about A
+      "A",
+      "B",
+
+      # footer
+    ]
+    "#}
+    )]
     fn test_order_array(#[case] start: &str, #[case] expected: &str) {
         let root_ast = parse(start).into_syntax().clone_for_update();
         for children in root_ast.children_with_tokens() {
@@ -1,6 +1,6 @@
 use taplo::parser::parse;
 use taplo::syntax::SyntaxElement;
-use taplo::syntax::SyntaxKind::{ARRAY, COMMA, ENTRY, KEY, NEWLINE, STRING, VALUE};
+use taplo::syntax::SyntaxKind::{ARRAY, COMMA, COMMENT, ENTRY, KEY, NEWLINE, STRING, VALUE};
 
 pub fn make_string_node(text: &str) -> SyntaxElement {
     let expr = &format!("a = \"{}\"", text.replace('"', "\\\""));
@@ -22,13 +22,18 @@ pub fn make_string_node(text: &str) -> SyntaxElement {
     panic!("Could not create string element for {text:?}")
 }
 
-pub fn make_empty_newline() -> SyntaxElement {
-    for root in parse("\n\n").into_syntax().clone_for_update().children_with_tokens() {
+/// A newline followed by `count` empty lines.
+pub fn make_blank_lines(count: usize) -> SyntaxElement {
+    for root in parse(&"\n".repeat(count + 1))
+        .into_syntax()
+        .clone_for_update()
+        .children_with_tokens()
+    {
         if root.kind() == NEWLINE {
             return root;
         }
     }
-    panic!("Could not create empty newline");
+    panic!("Could not create blank lines");
 }
 
 pub fn make_newline() -> SyntaxElement {
@@ -40,6 +45,16 @@ pub fn make_newline() -> SyntaxElement {
     panic!("Could not create newline");
 }
 
+/// The comment written as `text`, e.g. `# note`.
+pub fn make_comment(text: &str) -> SyntaxElement {
+    for root in parse(text).into_syntax().clone_for_update().children_with_tokens() {
+        if root.kind() == COMMENT {
+            return root;
+        }
+    }
+    panic!("Could not create comment {text}");
+}
+
 pub fn make_comma() -> SyntaxElement {
     for root in parse("a=[1,2]").into_syntax().clone_for_update().children_with_tokens() {
         if root.kind() == ENTRY {
@@ -118,6 +133,31 @@ pub fn make_array_entry(key: &str) -> SyntaxElement {
     panic!("Could not create array");
 }
 
+/// The `value` written as a basic string.
+pub fn basic_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The value of an array holding the `values` as basic strings.
+pub fn make_string_array(values: &[String]) -> SyntaxElement {
+    let items = values.iter().map(|v| basic_string(v)).collect::<Vec<String>>();
+    let txt = format!("a = [{}]", items.join(", "));
+    for root in parse(txt.as_str())
+        .into_syntax()
+        .clone_for_update()
+        .children_with_tokens()
+    {
+        if root.kind() == ENTRY {
+            for value in root.as_node().unwrap().children_with_tokens() {
+                if value.kind() == VALUE {
+                    return value;
+                }
+            }
+        }
+    }
+    panic!("Could not create array of {values:?}");
+}
+
 pub fn make_entry_of_string(key: &String, value: &String) -> SyntaxElement {
     let txt = format!("{key} = \"{value}\"\n");
     for root in parse(txt.as_str())
@@ -132,6 +172,16 @@ pub fn make_entry_of_string(key: &String, value: &String) -> SyntaxElement {
     panic!("Could not create entry of string");
 }
 
+/// The entry written as `text`, e.g. `a = [1, 2]`.
+pub fn make_entry(text: &str) -> SyntaxElement {
+    for root in parse(text).into_syntax().clone_for_update().children_with_tokens() {
+        if root.kind() == ENTRY {
+            return root;
+        }
+    }
+    panic!("Could not create entry {text}");
+}
+
 pub fn make_table_entry(key: &str) -> Vec<SyntaxElement> {
     let txt = format!("[{key}]\n");
     let mut res = Vec::<SyntaxElement>::new();
@@ -0,0 +1,27 @@
+use taplo::dom::node::DomNode;
+use taplo::dom::Node;
+use taplo::rowan::TextRange;
+use taplo::syntax::SyntaxElement;
+
+/// The node below the root at the path of keys, `None` when any of them is missing.
+pub fn lookup(root: &Node, path: &[&str]) -> Option<Node> {
+    path.iter()
+        .try_fold(root.clone(), |node, key| node.as_table().and_then(|t| t.get(*key)))
+}
+
+/// The byte range of the node within the document, empty for nodes not backed by it.
+pub fn range_of(node: &Node) -> TextRange {
+    node.syntax().map(SyntaxElement::text_range).unwrap_or_default()
+}
+
+/// The string values of the array, with the node holding each.
+pub fn strings(node: Option<&Node>) -> Vec<(String, Node)> {
+    node.and_then(Node::as_array).map_or_else(Vec::new, |array| {
+        array
+            .items()
+            .read()
+            .iter()
+            .filter_map(|item| item.as_str().map(|s| (String::from(s.value()), item.clone())))
+            .collect()
+    })
+}
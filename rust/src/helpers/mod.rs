@@ -1,5 +1,6 @@
 pub mod array;
 pub mod create;
+pub mod dom;
 pub mod pep508;
 pub mod string;
 pub mod table;
@@ -52,6 +52,15 @@ pub fn format_requirement(value: &str, keep_full_version: bool) -> String {
     result
 }
 
+/// Format the value if it is a requirement, otherwise (e.g. a path or an installer option) keep it as is.
+pub fn format_requirement_or_keep(value: &str, keep_full_version: bool) -> String {
+    if Requirement::from_str(value).is_ok() {
+        format_requirement(value, keep_full_version)
+    } else {
+        String::from(value)
+    }
+}
+
 fn handle_marker(marker: &MarkerTree, result: &mut String, nested: bool) {
     match marker {
         MarkerTree::Expression(e) => {
@@ -82,9 +91,14 @@ fn handle_tree(result: &mut String, nested: bool, elements: &[MarkerTree], x: &s
     }
 }
 
+/// The canonical name of the package the requirement is for, the value as is if it is not a valid requirement.
 pub fn get_canonic_requirement_name(value: &str) -> String {
-    let req = Requirement::from_str(value).unwrap();
-    req.name.to_string()
+    Requirement::from_str(value).map_or_else(|_| String::from(value), |req| req.name.to_string())
+}
+
+/// The canonical form of the package name, the name lowercased if it is not a valid one.
+pub fn get_canonic_package_name(name: &str) -> String {
+    Requirement::from_str(name).map_or_else(|_| name.to_lowercase(), |req| req.name.to_string())
 }
 
 #[cfg(test)]
@@ -96,6 +110,7 @@ mod tests {
     #[rstest]
     #[case::lowercase("A", "a")]
     #[case::replace_dot_with_dash("a.b", "a-b")]
+    #[case::invalid("requests>=", "requests>=")]
     fn test_get_canonic_requirement_name(#[case] start: &str, #[case] expected: &str) {
         assert_eq!(get_canonic_requirement_name(start), expected);
     }
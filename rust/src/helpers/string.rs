@@ -35,8 +35,21 @@ where
         count += 1;
         let kind = child.kind();
         if [STRING, STRING_LITERAL, MULTI_LINE_STRING, MULTI_LINE_STRING_LITERAL].contains(&kind) {
-            let found_str_value = load_text(child.as_token().unwrap().text(), kind);
-            let output = transform(found_str_value.as_str());
+            let mut found_str_value = load_text(child.as_token().unwrap().text(), kind);
+            // the value is rewritten as a basic string, keep what literal and multi line strings mean within it
+            if [STRING_LITERAL, MULTI_LINE_STRING_LITERAL].contains(&kind) {
+                found_str_value = found_str_value.replace('\\', "\\\\");
+            }
+            if [MULTI_LINE_STRING, MULTI_LINE_STRING_LITERAL].contains(&kind) {
+                found_str_value = found_str_value
+                    .strip_prefix('\n')
+                    .or_else(|| found_str_value.strip_prefix("\r\n"))
+                    .unwrap_or(&found_str_value)
+                    .to_string();
+            }
+            let output = transform(found_str_value.as_str())
+                .replace('\r', "\\r")
+                .replace('\n', "\\n");
 
             changed = output != found_str_value || kind != STRING;
             if changed {
@@ -1,13 +1,19 @@
 use std::cell::{RefCell, RefMut};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::iter::zip;
 use std::ops::Index;
 
-use taplo::syntax::SyntaxKind::{ENTRY, IDENT, KEY, NEWLINE, TABLE_ARRAY_HEADER, TABLE_HEADER, VALUE};
+use globset::{Glob, GlobMatcher};
+use lexical_sort::natural_lexical_cmp;
+use taplo::syntax::SyntaxKind::{
+    COMMENT, ENTRY, IDENT, INLINE_TABLE, KEY, MULTI_LINE_STRING, MULTI_LINE_STRING_LITERAL, NEWLINE, STRING,
+    STRING_LITERAL, TABLE_ARRAY_HEADER, TABLE_HEADER, VALUE, WHITESPACE,
+};
 use taplo::syntax::{SyntaxElement, SyntaxNode};
 use taplo::HashSet;
 
-use crate::helpers::create::{make_empty_newline, make_key, make_newline, make_table_entry};
+use crate::helpers::create::{make_blank_lines, make_key, make_newline, make_table_entry};
 use crate::helpers::string::load_text;
 
 #[derive(Debug)]
@@ -29,15 +35,72 @@ impl Tables {
         }
     }
 
-    pub fn from_ast(root_ast: &SyntaxNode) -> Self {
+    /// True if `name` or one of its parents is an array of tables.
+    pub fn within_array(&self, name: &str) -> bool {
+        let parts = split_name(name);
+        (1..=parts.len()).any(|at| {
+            self.header_to_pos.get(&parts[..at].join(".")).is_some_and(|positions| {
+                positions.iter().any(|p| {
+                    header_of(&self.table_set[*p].borrow()).map(SyntaxElement::kind) == Some(TABLE_ARRAY_HEADER)
+                })
+            })
+        })
+    }
+
+    /// Position of the table `name`, creating an empty table for it if needed; `None` for arrays of tables.
+    pub fn ensure(&mut self, name: &str) -> Option<usize> {
+        if self.within_array(name) {
+            return None;
+        }
+        match self.header_to_pos.get(name) {
+            Some(positions) if positions.len() == 1 => {
+                let position = positions[0];
+                if self.table_set[position].borrow().is_empty() {
+                    self.table_set[position].replace(make_table_entry(name));
+                }
+                Some(position)
+            }
+            Some(_) => None,
+            None => {
+                self.header_to_pos
+                    .insert(String::from(name), vec![self.table_set.len()]);
+                self.table_set.push(RefCell::new(make_table_entry(name)));
+                Some(self.table_set.len() - 1)
+            }
+        }
+    }
+
+    /// Split the document into tables, the comments directly above a header belong to that header. With
+    /// `keep_banner_comments` a comment block separated from the header by a single blank line (a banner introducing
+    /// the table) also stays with it.
+    pub fn from_ast(root_ast: &SyntaxNode, keep_banner_comments: bool) -> Self {
         let mut header_to_pos = HashMap::<String, Vec<usize>>::new();
         let mut table_set = Vec::<RefCell<Vec<SyntaxElement>>>::new();
         let entry_set = RefCell::new(Vec::<SyntaxElement>::new());
+        let mut array_names = HashSet::<String>::new();
         let mut table_kind = TABLE_HEADER;
         let mut add_to_table_set = |kind| {
             let mut entry_set_borrow = entry_set.borrow_mut();
             if !entry_set_borrow.is_empty() {
-                let table_name = get_table_name(&entry_set_borrow[0]);
+                let table_name = header_of(&entry_set_borrow).map(get_table_name).unwrap_or_default();
+                let parent_array = array_names
+                    .iter()
+                    .filter(|a| table_name.starts_with(format!("{a}.").as_str()))
+                    .max_by_key(|a| a.len());
+                if let Some(parent_array) = parent_array {
+                    // sub-tables of an array of tables belong to its last element, so they must move together with it
+                    let pos = header_to_pos[parent_array].last().unwrap();
+                    let mut res = table_set.index(*pos).borrow_mut();
+                    if res.last().unwrap().kind() != NEWLINE {
+                        res.push(make_newline());
+                    }
+                    res.extend(entry_set_borrow.clone());
+                    entry_set_borrow.clear();
+                    return;
+                }
+                if kind == TABLE_ARRAY_HEADER {
+                    array_names.insert(table_name.clone());
+                }
                 let indexes = header_to_pos.entry(table_name).or_default();
                 if kind == TABLE_ARRAY_HEADER || (kind == TABLE_HEADER && indexes.is_empty()) {
                     indexes.push(table_set.len());
@@ -53,18 +116,21 @@ impl Tables {
                     if res.last().unwrap().kind() != NEWLINE {
                         res.push(make_newline());
                     }
-                    res.extend(
-                        new.into_iter()
-                            .skip_while(|x| [NEWLINE, TABLE_HEADER].contains(&x.kind())),
-                    );
+                    let header_at = new.iter().position(|x| x.kind() == TABLE_HEADER).unwrap();
+                    let body = new.split_off(header_at + 1);
+                    new.pop();
+                    res.extend(new.into_iter().skip_while(|x| x.kind() == NEWLINE));
+                    res.extend(body.into_iter().skip_while(|x| x.kind() == NEWLINE));
                 }
                 entry_set_borrow.clear();
             }
         };
         for c in root_ast.children_with_tokens() {
             if [TABLE_ARRAY_HEADER, TABLE_HEADER].contains(&c.kind()) {
+                let leading = split_header_comments(&mut entry_set.borrow_mut(), keep_banner_comments);
                 add_to_table_set(table_kind);
                 table_kind = c.kind();
+                entry_set.borrow_mut().extend(leading);
             }
             entry_set.borrow_mut().push(c);
         }
@@ -75,9 +141,19 @@ impl Tables {
         }
     }
 
-    pub fn reorder(&self, root_ast: &SyntaxNode, order: &[&str]) {
+    /// Reorder the tables by `order`, separating tables of different groups (see [`get_key`]) by
+    /// `blank_lines_between_groups` empty lines, and tables within a group by `blank_lines_between_sub_tables` (or as
+    /// written when not set).
+    pub fn reorder(
+        &self,
+        root_ast: &SyntaxNode,
+        order: &[&str],
+        sort_unknown_tools: bool,
+        blank_lines_between_groups: usize,
+        blank_lines_between_sub_tables: Option<usize>,
+    ) {
         let mut to_insert = Vec::<SyntaxElement>::new();
-        let order = calculate_order(&self.header_to_pos, &self.table_set, order);
+        let order = calculate_order(&self.header_to_pos, &self.table_set, order, sort_unknown_tools);
         let mut next = order.clone();
         if !next.is_empty() {
             next.remove(0);
@@ -91,13 +167,18 @@ impl Tables {
                     if name.is_empty() && last.kind() == NEWLINE && got.len() == 1 {
                         continue;
                     }
+                    let blank_lines = if get_key(name) == get_key(next_name) {
+                        blank_lines_between_sub_tables
+                    } else {
+                        Some(blank_lines_between_groups)
+                    };
                     let mut add = got.clone();
-                    if get_key(name) != get_key(next_name) {
+                    if let Some(count) = blank_lines {
                         if last.kind() == NEWLINE {
-                            // replace existing newline to ensure single newline
+                            // replace existing newline to ensure the requested spacing
                             add.pop();
                         }
-                        add.push(make_empty_newline());
+                        add.push(make_blank_lines(count));
                     }
                     to_insert.extend(add);
                 }
@@ -105,18 +186,36 @@ impl Tables {
         }
         root_ast.splice_children(0..root_ast.children_with_tokens().count(), to_insert);
     }
+
+    /// Write the tables back into the document in their current order.
+    pub fn write_back(&self, root_ast: &SyntaxNode) {
+        let entries = self
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..root_ast.children_with_tokens().count(), entries);
+    }
 }
 fn calculate_order(
     header_to_pos: &HashMap<String, Vec<usize>>,
     table_set: &[RefCell<Vec<SyntaxElement>>],
     ordering: &[&str],
+    sort_unknown_tools: bool,
 ) -> Vec<String> {
     let max_ordering = ordering.len() * 2;
-    let key_to_pos = ordering
-        .iter()
-        .enumerate()
-        .map(|(k, v)| (v, k * 2))
-        .collect::<HashMap<&&str, usize>>();
+    let mut key_to_pos = HashMap::<&str, usize>::new();
+    let mut patterns = Vec::<(GlobMatcher, usize)>::new();
+    for (at, key) in ordering.iter().enumerate() {
+        if key.contains(['*', '?', '[']) {
+            if let Ok(glob) = Glob::new(key) {
+                patterns.push((glob.compile_matcher(), at * 2));
+                continue;
+            }
+        }
+        // the first occurrence wins, so that user supplied entries can override the defaults
+        key_to_pos.entry(key).or_insert(at * 2);
+    }
 
     let mut header_pos: Vec<(String, usize)> = header_to_pos
         .clone()
@@ -125,19 +224,22 @@ fn calculate_order(
         .map(|(k, v)| (k, *v.iter().min().unwrap()))
         .collect();
 
-    header_pos.sort_by_cached_key(|(k, file_pos)| -> (usize, usize) {
+    header_pos.sort_by_cached_key(|(k, file_pos)| -> (usize, String, usize, usize) {
         let key = get_key(k);
-        let pos = key_to_pos.get(&key.as_str());
-
-        (
-            if let Some(&pos) = pos {
-                let offset = usize::from(key != *k);
-                pos + offset
-            } else {
-                max_ordering
-            },
-            *file_pos,
-        )
+        let pos = key_to_pos.get(key.as_str()).copied().or_else(|| {
+            patterns
+                .iter()
+                .filter(|(matcher, _)| matcher.is_match(key.as_str()))
+                .map(|(_, pos)| *pos)
+                .min()
+        });
+        match pos {
+            Some(pos) => (pos + usize::from(key != *k), String::new(), 0, *file_pos),
+            None if sort_unknown_tools && key.starts_with("tool.") => {
+                (max_ordering, key.to_lowercase(), usize::from(key != *k), *file_pos)
+            }
+            None => (max_ordering + 1, String::new(), 0, *file_pos),
+        }
     });
     header_pos.into_iter().map(|(k, _)| k).collect()
 }
@@ -154,9 +256,23 @@ fn get_key(k: &str) -> String {
     String::from(k)
 }
 
+/// Reorder the keys of a table: keys matching an entry of `order` (exactly or as a dotted prefix) come in that order,
+/// sorted within the entry, the others follow as written. Comment lines directly above a key move with it. A comment
+/// block separated from the key below by a blank line describes a section instead: directly under the header it stays
+/// there, otherwise it is placed above whichever key of its section (the keys up to the next section comment) comes
+/// first.
 pub fn reorder_table_keys(table: &mut RefMut<Vec<SyntaxElement>>, order: &[&str]) {
     let (size, mut to_insert) = (table.len(), Vec::<SyntaxElement>::new());
-    let (key_to_position, key_set) = load_keys(table);
+    let (key_to_position, mut key_set, mut sections) = load_keys(table);
+    // the blank lines ending the table separate it from the next one, these stay at the end
+    let trailing = key_set
+        .last_mut()
+        .filter(|last| last.last().is_some_and(is_blank_line))
+        .map(|last| {
+            let blank = last.pop().unwrap();
+            last.push(make_newline());
+            blank
+        });
     let mut handled_positions = HashSet::<usize>::new();
     for current_key in order {
         let mut matching_keys = key_to_position
@@ -174,28 +290,68 @@ pub fn reorder_table_keys(table: &mut RefMut<Vec<SyntaxElement>>, order: &[&str]
         matching_keys.sort_by_key(|key| key.to_lowercase().replace('"', ""));
         for key in matching_keys {
             let position = key_to_position[key];
+            insert_section(&mut to_insert, &mut sections, position);
             to_insert.extend(key_set[position].clone());
             handled_positions.insert(position);
         }
     }
     for (position, entries) in key_set.into_iter().enumerate() {
         if !handled_positions.contains(&position) {
+            insert_section(&mut to_insert, &mut sections, position);
             to_insert.extend(entries);
         }
     }
+    if let Some(trailing) = trailing {
+        if to_insert.last().map(SyntaxElement::kind) == Some(NEWLINE) {
+            to_insert.pop();
+        }
+        to_insert.push(trailing);
+    }
     table.splice(0..size, to_insert);
 }
 
-fn load_keys(table: &[SyntaxElement]) -> (HashMap<String, usize>, Vec<Vec<SyntaxElement>>) {
+/// Insert the section comment covering the key at `position`, unless an earlier key of the section already did.
+fn insert_section(
+    to_insert: &mut Vec<SyntaxElement>,
+    sections: &mut BTreeMap<usize, Vec<SyntaxElement>>,
+    position: usize,
+) {
+    let Some((&start, _)) = sections.range(..=position).next_back() else {
+        return;
+    };
+    let Some(section) = sections.get_mut(&start).filter(|s| !s.is_empty()) else {
+        return;
+    };
+    let mut section = std::mem::take(section);
+    if section.first().map(SyntaxElement::kind) == Some(NEWLINE) {
+        // the blank line that separated the section from the keys above
+        let gap = section.remove(0);
+        if to_insert.last().map(SyntaxElement::kind) == Some(NEWLINE) {
+            to_insert.pop();
+            to_insert.push(gap);
+        }
+    }
+    to_insert.extend(section);
+}
+
+type KeySets = (
+    HashMap<String, usize>,
+    Vec<Vec<SyntaxElement>>,
+    BTreeMap<usize, Vec<SyntaxElement>>,
+);
+
+/// Split the table into the elements of each key (with the comment lines directly above it), and the section comments
+/// (followed by a blank line) preceding a key by its position.
+fn load_keys(table: &[SyntaxElement]) -> KeySets {
     let mut key_to_pos = HashMap::<String, usize>::new();
     let mut key_set = Vec::<Vec<SyntaxElement>>::new();
-    let entry_set = RefCell::new(Vec::<SyntaxElement>::new());
-    let mut add_to_key_set = |k| {
-        let mut entry_set_borrow = entry_set.borrow_mut();
-        if !entry_set_borrow.is_empty() {
+    let mut sections = BTreeMap::<usize, Vec<SyntaxElement>>::new();
+    let mut entry_set = Vec::<SyntaxElement>::new();
+    let mut add_to_key_set = |k, entry_set: &mut Vec<SyntaxElement>, key_set: &mut Vec<Vec<SyntaxElement>>| {
+        if !entry_set.is_empty() {
             key_to_pos.insert(k, key_set.len());
-            key_set.push(entry_set_borrow.clone());
-            entry_set_borrow.clear();
+            key_set.push(entry_set.clone());
+            entry_set.clear();
         }
     };
     let mut key = String::new();
@@ -204,8 +360,25 @@ fn load_keys(table: &[SyntaxElement]) -> (HashMap<String, usize>, Vec<Vec<Syntax
         let kind = element.kind();
         if kind == ENTRY {
             if cutoff {
-                add_to_key_set(key.clone());
+                add_to_key_set(key.clone(), &mut entry_set, &mut key_set);
                 cutoff = false;
+            } else if let Some(at) = entry_set.iter().rposition(is_blank_line) {
+                let rest = entry_set.split_off(at + 1);
+                let mut section = std::mem::replace(&mut entry_set, rest);
+                match key_set.as_mut_slice() {
+                    [header] if header_of(header).is_some() => header.extend(section),
+                    [.., previous] => {
+                        // the blank line above the section moves with it
+                        if previous.last().is_some_and(is_blank_line) {
+                            section.insert(0, previous.pop().unwrap());
+                            previous.push(make_newline());
+                        }
+                        sections.insert(key_set.len(), section);
+                    }
+                    [] => {
+                        sections.insert(key_set.len(), section);
+                    }
+                }
             }
             for e in element.as_node().unwrap().children_with_tokens() {
                 if e.kind() == KEY {
@@ -217,14 +390,49 @@ fn load_keys(table: &[SyntaxElement]) -> (HashMap<String, usize>, Vec<Vec<Syntax
         if [ENTRY, TABLE_HEADER, TABLE_ARRAY_HEADER].contains(&kind) {
             cutoff = true;
         }
-        entry_set.borrow_mut().push(element.clone());
+        entry_set.push(element.clone());
         if cutoff && kind == NEWLINE {
-            add_to_key_set(key.clone());
+            add_to_key_set(key.clone(), &mut entry_set, &mut key_set);
             cutoff = false;
         }
     }
-    add_to_key_set(key);
-    (key_to_pos, key_set)
+    add_to_key_set(key, &mut entry_set, &mut key_set);
+    (key_to_pos, key_set, sections)
+}
+
+fn is_blank_line(element: &SyntaxElement) -> bool {
+    element.kind() == NEWLINE && element.as_token().is_some_and(|t| t.text().matches('\n').count() > 1)
+}
+
+/// The header of a table, which may be preceded by the comments attached to it.
+pub fn header_of(table: &[SyntaxElement]) -> Option<&SyntaxElement> {
+    table
+        .iter()
+        .find(|e| [TABLE_HEADER, TABLE_ARRAY_HEADER].contains(&e.kind()))
+}
+
+/// Split off the comment lines at the end of `entries` that sit directly above the table header following them, so
+/// that they move together with that header.
+fn split_header_comments(entries: &mut Vec<SyntaxElement>, keep_banner_comments: bool) -> Vec<SyntaxElement> {
+    let newlines = |e: &SyntaxElement| e.as_token().map_or(0, |t| t.text().matches('\n').count());
+    let (mut at, mut split_at) = (entries.len(), entries.len());
+    while at >= 2 && entries[at - 1].kind() == NEWLINE && entries[at - 2].kind() == COMMENT {
+        let blank_lines = newlines(&entries[at - 1]) - 1;
+        // a banner may be separated from the header by one blank line, comment lines within a block may not
+        let is_banner = split_at == entries.len() && keep_banner_comments;
+        if blank_lines > usize::from(is_banner) {
+            break;
+        }
+        at -= 2;
+        while at > 0 && entries[at - 1].kind() == WHITESPACE {
+            at -= 1;
+        }
+        if at > 0 && entries[at - 1].kind() != NEWLINE {
+            break;
+        }
+        split_at = at;
+    }
+    entries.split_off(split_at)
 }
 
 pub fn get_table_name(entry: &SyntaxElement) -> String {
@@ -256,63 +464,352 @@ where
     }
 }
 
-pub fn collapse_sub_tables(tables: &mut Tables, name: &str) {
-    let h2p = tables.header_to_pos.clone();
-    let sub_name_prefix = format!("{name}.");
-    let sub_table_keys: Vec<&String> = h2p.keys().filter(|s| s.starts_with(sub_name_prefix.as_str())).collect();
-    if sub_table_keys.is_empty() {
+/// Reorder the keys of the inline table held by the value `node`: keys listed in `order` first, in that order, then the
+/// rest sorted. The separators between the entries stay in place.
+pub fn reorder_inline_table_keys(node: &SyntaxNode, order: &[&str]) {
+    for inline_table in node.children().filter(|n| n.kind() == INLINE_TABLE) {
+        let mut children = inline_table.children_with_tokens().collect::<Vec<SyntaxElement>>();
+        let positions = (0..children.len())
+            .filter(|at| children[*at].kind() == ENTRY)
+            .collect::<Vec<usize>>();
+        let mut entries = positions
+            .iter()
+            .map(|at| children[*at].clone())
+            .collect::<Vec<SyntaxElement>>();
+        entries.sort_by_cached_key(|entry| {
+            let key = key_parts(entry).join(".");
+            let rank = order
+                .iter()
+                .position(|o| *o == key || key.starts_with(&format!("{o}.")))
+                .unwrap_or(order.len());
+            (rank, key.to_lowercase())
+        });
+        for (at, entry) in zip(&positions, entries) {
+            children[*at] = entry;
+        }
+        inline_table.splice_children(0..children.len(), children);
+    }
+}
+
+/// Sort the elements of the array of tables `name` by the value of their `key` entry (elements without it keep their
+/// relative order, after the others), and order the keys within each element with `key` first and the rest sorted.
+/// Sub-tables declared under an element move together with it.
+pub fn sort_table_array(tables: &Tables, name: &str, key: &str) {
+    let Some(positions) = tables.header_to_pos.get(name) else {
         return;
+    };
+    let mut elements = Vec::<(Option<String>, Vec<SyntaxElement>)>::new();
+    for position in positions {
+        let element = tables.table_set[*position].borrow().clone();
+        if header_of(&element).map(SyntaxElement::kind) != Some(TABLE_ARRAY_HEADER) {
+            return;
+        }
+        let header_at = element.iter().position(|e| e.kind() == TABLE_ARRAY_HEADER).unwrap();
+        let split_at = element
+            .iter()
+            .skip(header_at + 1)
+            .position(|e| [TABLE_HEADER, TABLE_ARRAY_HEADER].contains(&e.kind()))
+            .map_or(element.len(), |at| at + header_at + 1);
+        let (head, tail) = element.split_at(split_at);
+        let head = RefCell::new(head.to_vec());
+        let (mut keys, mut value) = (Vec::<String>::new(), None);
+        for_entries(&head.borrow(), &mut |entry_key, node| {
+            if entry_key == key {
+                value = node
+                    .children_with_tokens()
+                    .find(|e| e.kind() != WHITESPACE && e.kind() != COMMENT)
+                    .map(|e| match e.kind() {
+                        STRING | STRING_LITERAL | MULTI_LINE_STRING | MULTI_LINE_STRING_LITERAL => {
+                            load_text(e.as_token().unwrap().text(), e.kind())
+                        }
+                        _ => e.to_string(),
+                    });
+            } else {
+                keys.push(entry_key);
+            }
+        });
+        keys.sort_by(|a, b| natural_lexical_cmp(a, b));
+        let order = [String::new(), String::from(key)]
+            .into_iter()
+            .chain(keys)
+            .collect::<Vec<String>>();
+        reorder_table_keys(
+            &mut head.borrow_mut(),
+            &order.iter().map(String::as_str).collect::<Vec<&str>>(),
+        );
+        let mut element = head.into_inner();
+        if element.last().map(SyntaxElement::kind) != Some(NEWLINE) {
+            element.push(make_newline());
+        }
+        element.extend(tail.iter().cloned());
+        elements.push((value, element));
+    }
+    elements.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => natural_lexical_cmp(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    for (position, (_, element)) in zip(positions, elements) {
+        tables.table_set[*position].replace(element);
     }
-    if !tables.header_to_pos.contains_key(name) {
-        tables
+}
+
+/// Move the tables `names` (all the elements, for an array of tables) into the positions they occupy in the given
+/// order, along with the tables nested under them. The blank lines after a table separate it from the next one, so
+/// they stay at their position.
+pub fn reorder_tables(tables: &mut Tables, names: &[String]) {
+    let mut claimed = HashSet::<usize>::default();
+    let groups = names
+        .iter()
+        .map(|name| {
+            let prefix = format!("{name}.");
+            let mut group = tables
+                .header_to_pos
+                .iter()
+                .filter(|(k, _)| *k == name || k.starts_with(prefix.as_str()))
+                .flat_map(|(k, v)| v.iter().map(|p| (*p, k.clone())))
+                .filter(|(p, _)| !tables.table_set[*p].borrow().is_empty())
+                .collect::<Vec<(usize, String)>>();
+            group.sort();
+            group.retain(|(p, _)| claimed.insert(*p));
+            group
+        })
+        .collect::<Vec<_>>();
+    let mut positions = groups.iter().flatten().map(|(p, _)| *p).collect::<Vec<usize>>();
+    positions.sort_unstable();
+    let mut separators = Vec::new();
+    for position in &positions {
+        let mut content = tables.table_set[*position].borrow_mut();
+        let separator = match content.last() {
+            Some(last) if last.kind() == NEWLINE => content.pop(),
+            _ => Some(make_newline()),
+        };
+        separators.push(separator);
+    }
+    let mut contents = Vec::new();
+    for (position, name) in groups.into_iter().flatten() {
+        if let Some(positions) = tables.header_to_pos.get_mut(&name) {
+            positions.retain(|p| *p != position);
+        }
+        contents.push((name, tables.table_set[position].take()));
+    }
+    for ((position, (name, mut content)), separator) in zip(zip(&positions, contents), separators) {
+        content.extend(separator);
+        tables.table_set[*position].replace(content);
+        let name_positions = tables.header_to_pos.entry(name).or_default();
+        name_positions.push(*position);
+        name_positions.sort_unstable();
+    }
+}
+
+pub fn split_name(name: &str) -> Vec<String> {
+    let mut parts = Vec::<String>::new();
+    let mut current = String::new();
+    let (mut quote, mut escaped) = (None::<char>, false);
+    for c in name.chars() {
+        if let Some(q) = quote {
+            current.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && q == '"' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+            current.push(c);
+        } else if c == '.' {
+            parts.push(current.trim().to_string());
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+pub fn key_parts(entry: &SyntaxElement) -> Vec<String> {
+    for child in entry.as_node().unwrap().children_with_tokens() {
+        if child.kind() == KEY {
+            return child
+                .as_node()
+                .unwrap()
+                .children_with_tokens()
+                .filter(|e| e.kind() == IDENT)
+                .map(|e| e.as_token().unwrap().text().to_string())
+                .collect();
+        }
+    }
+    vec![]
+}
+
+#[allow(clippy::range_plus_one)]
+pub fn set_key(entry: &SyntaxElement, key: &str) {
+    let node = entry.as_node().unwrap();
+    if let Some(at) = node.children_with_tokens().position(|e| e.kind() == KEY) {
+        node.splice_children(at..at + 1, vec![make_key(key)]);
+    }
+}
+
+/// Fold every sub-table of `name` (e.g. `[project.urls]` for `project`) into dotted keys of `name`.
+pub fn collapse_sub_tables(tables: &mut Tables, name: &str) {
+    let prefix = format!("{name}.");
+    collapse_tables_where(tables, |sub| sub.starts_with(prefix.as_str()));
+}
+
+/// Fold sub-tables into their parent table, deepest first, for every sub-table name accepted by `should_fold`.
+pub fn collapse_tables_where<F>(tables: &mut Tables, should_fold: F)
+where
+    F: Fn(&str) -> bool,
+{
+    let depth_of = |name: &String| split_name(name).len();
+    let max_depth = tables.header_to_pos.keys().map(depth_of).max().unwrap_or(0);
+    for depth in (2..=max_depth).rev() {
+        let mut names = tables
             .header_to_pos
-            .insert(String::from(name), vec![tables.table_set.len()]);
-        tables.table_set.push(RefCell::new(make_table_entry(name)));
+            .iter()
+            .filter(|(k, v)| depth_of(k) == depth && !v.is_empty() && should_fold(k))
+            .map(|(k, v)| (v[0], k.clone()))
+            .collect::<Vec<(usize, String)>>();
+        names.sort();
+        for (_, name) in names {
+            fold_into_parent(tables, &name);
+        }
+    }
+}
+
+/// Fold a single sub-table into its parent table (created if missing) by prefixing its keys with the sub-table name.
+/// Arrays of tables (and tables nested within them) are left alone, as they cannot be expressed via dotted keys.
+fn fold_into_parent(tables: &mut Tables, name: &str) {
+    let parts = split_name(name);
+    if parts.len() < 2 || tables.within_array(name) {
+        return;
     }
-    let main_positions = tables.header_to_pos[name].clone();
-    if main_positions.len() != 1 {
+    let sub_positions = tables.header_to_pos[name].clone();
+    if sub_positions.len() != 1 || tables.table_set[sub_positions[0]].borrow().is_empty() {
         return;
     }
-    let mut main = tables.table_set[*main_positions.first().unwrap()].borrow_mut();
-    for key in sub_table_keys {
-        let sub_positions = tables.header_to_pos[key].clone();
-        if sub_positions.len() != 1 {
+    let parent = parts[..parts.len() - 1].join(".");
+    let Some(main_position) = tables.ensure(&parent) else {
+        return;
+    };
+    let sub_name = &parts[parts.len() - 1];
+    let mut main = tables.table_set[main_position].borrow_mut();
+    let mut sub = tables.table_set[sub_positions[0]].borrow_mut();
+    let trailing = if main.last().is_some_and(is_blank_line) {
+        let blank = main.pop();
+        main.push(make_newline());
+        blank
+    } else {
+        None
+    };
+    for child in sub.iter() {
+        let kind = child.kind();
+        if kind == TABLE_HEADER {
+            // a comment on the header line becomes a comment above the moved entries
+            if let Some(comment) = child
+                .as_node()
+                .unwrap()
+                .children_with_tokens()
+                .find(|e| e.kind() == COMMENT)
+            {
+                if main.last().unwrap().kind() != NEWLINE {
+                    main.push(make_newline());
+                }
+                main.push(comment);
+                main.push(make_newline());
+            }
             continue;
         }
-        let mut sub = tables.table_set[*sub_positions.first().unwrap()].borrow_mut();
-        let sub_name = key.strip_prefix(sub_name_prefix.as_str()).unwrap();
-        let mut header = false;
-        for child in sub.iter() {
-            let kind = child.kind();
-            if kind == TABLE_HEADER {
-                header = true;
-                continue;
-            }
-            if header && kind == NEWLINE {
-                continue;
-            }
-            if kind == ENTRY {
-                let mut to_insert = Vec::<SyntaxElement>::new();
-                let child_node = child.as_node().unwrap();
-                for mut entry in child_node.children_with_tokens() {
-                    if entry.kind() == KEY {
-                        for array_entry_value in entry.as_node().unwrap().children_with_tokens() {
-                            if array_entry_value.kind() == IDENT {
-                                let txt = load_text(array_entry_value.as_token().unwrap().text(), IDENT);
-                                entry = make_key(format!("{sub_name}.{txt}").as_str());
-                                break;
-                            }
-                        }
-                    }
-                    to_insert.push(entry);
-                }
-                child_node.splice_children(0..to_insert.len(), to_insert);
+        if kind == NEWLINE {
+            continue;
+        }
+        if kind == ENTRY {
+            let key = key_parts(child).join(".");
+            set_key(child, format!("{sub_name}.{key}").as_str());
+        }
+        if main.last().unwrap().kind() != NEWLINE {
+            main.push(make_newline());
+        }
+        main.push(child.clone());
+    }
+    // the blank lines separating the tables from the next one now follow the moved entries
+    if let Some(blank) = trailing.or_else(|| sub.last().filter(|e| is_blank_line(e)).cloned()) {
+        if main.last().unwrap().kind() == NEWLINE {
+            main.pop();
+        }
+        main.push(blank);
+    }
+    sub.clear();
+}
+
+/// Move dotted keys of the table `name` into sub-table headers (e.g. `urls.a = ""` within `[project]` becomes
+/// `a = ""` within `[project.urls]`), keeping the comments above and after each entry attached to it. Returns the
+/// names of the sub-tables that received entries.
+pub fn expand_sub_tables(tables: &mut Tables, name: &str) -> Vec<String> {
+    let mut touched = Vec::<String>::new();
+    if name.is_empty() || tables.within_array(name) {
+        return touched;
+    }
+    let positions = tables.header_to_pos[name].clone();
+    if positions.len() != 1 {
+        return touched;
+    }
+    let elements = tables.table_set[positions[0]].borrow().clone();
+    let (mut keep, mut pending) = (Vec::<SyntaxElement>::new(), Vec::<SyntaxElement>::new());
+    let mut moved = Vec::<(String, usize, Vec<SyntaxElement>)>::new();
+    let mut skip_newline = false;
+    for element in elements {
+        let kind = element.kind();
+        if kind == COMMENT || (kind == WHITESPACE && !pending.is_empty()) {
+            pending.push(element);
+        } else if kind == NEWLINE {
+            if skip_newline {
+                skip_newline = false;
+            } else if !pending.is_empty() && element.as_token().unwrap().text().matches('\n').count() == 1 {
+                pending.push(element);
+            } else {
+                keep.append(&mut pending);
+                keep.push(element);
             }
-            if main.last().unwrap().kind() != NEWLINE {
-                main.push(make_newline());
+        } else if kind == ENTRY {
+            let parts = key_parts(&element);
+            let target = format!("{name}.{}", parts[0]);
+            // the entry stays when there is no single table to move it to (e.g. within an array of tables)
+            let position = if parts.len() < 2 { None } else { tables.ensure(&target) };
+            if let Some(position) = position {
+                set_key(&element, parts[1..].join(".").as_str());
+                pending.push(element);
+                moved.push((target, position, pending.clone()));
+                pending.clear();
+                skip_newline = true;
+            } else {
+                keep.append(&mut pending);
+                keep.push(element);
             }
-            main.push(child.clone());
+        } else {
+            keep.append(&mut pending);
+            keep.push(element);
+        }
+    }
+    keep.append(&mut pending);
+    if moved.is_empty() {
+        return touched;
+    }
+    tables.table_set[positions[0]].replace(keep);
+    for (target, position, group) in moved {
+        let mut table = tables.table_set[position].borrow_mut();
+        if table.last().unwrap().kind() != NEWLINE {
+            table.push(make_newline());
+        }
+        table.extend(group);
+        table.push(make_newline());
+        if !touched.contains(&target) {
+            touched.push(target);
         }
-        sub.clear();
     }
+    touched
 }
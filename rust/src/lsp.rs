@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+
+use serde_json::{json, Value};
+use taplo::dom::Node;
+use taplo::parser::parse;
+use taplo::syntax::SyntaxKind::TABLE_HEADER;
+
+use crate::diagnostics::{diagnose, Severity};
+use crate::edits::TextEdit;
+use crate::helpers::create::make_string_node;
+use crate::helpers::dom::{lookup, range_of, strings};
+use crate::helpers::pep508::format_requirement_or_keep;
+use crate::helpers::table::get_table_name;
+use crate::{format_range, format_toml, format_toml_edits, Settings};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const REQUEST_FAILED: i64 = -32803;
+
+/// Serve the Language Server Protocol over `input` and `output` (the standard streams of the server process) until
+/// the client asks to exit or closes the input. Documents are formatted with the `opt` settings.
+///
+/// # Errors
+///
+/// Will return an error message if reading or writing the streams fails.
+pub fn serve(mut input: impl BufRead, output: impl Write, opt: &Settings) -> Result<(), String> {
+    let mut server = Server {
+        opt,
+        output,
+        documents: HashMap::new(),
+    };
+    while let Some(message) = read_message(&mut input)? {
+        // a malformed message has no id to answer to, the error goes out with a null one
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                server.send(&json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": e}}))?;
+                continue;
+            }
+        };
+        if message["method"] == "exit" {
+            break;
+        }
+        server.handle(&message)?;
+    }
+    Ok(())
+}
+
+/// Read the next message, `None` once the input is closed. The message is an error when its headers or content are
+/// malformed, while failing to read the input ends the session.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Result<Value, String>>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                match value.trim().parse::<usize>() {
+                    Ok(value) => length = Some(value),
+                    Err(e) => return Ok(Some(Err(format!("invalid Content-Length header: {e}")))),
+                }
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Ok(Some(Err(String::from("message without a Content-Length header"))));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| format!("invalid message: {e}")),
+    ))
+}
+
+struct Server<'a, W> {
+    opt: &'a Settings,
+    output: W,
+    documents: HashMap<String, String>,
+}
+
+impl<W: Write> Server<'_, W> {
+    fn handle(&mut self, message: &Value) -> Result<(), String> {
+        let (method, params) = (message["method"].as_str().unwrap_or_default(), &message["params"]);
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1, // the client sends the full text on each change
+                    "documentFormattingProvider": true,
+                    "documentRangeFormattingProvider": true,
+                    "codeActionProvider": {"codeActionKinds": ["quickfix"]},
+                },
+                "serverInfo": {"name": "pyproject-fmt", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = params["textDocument"]["text"].as_str().or_else(|| {
+                    params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str())
+                });
+                if let Some(text) = text {
+                    self.documents.insert(String::from(uri), String::from(text));
+                }
+                return self.publish_diagnostics(uri);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return self.notify(
+                    "textDocument/publishDiagnostics",
+                    &json!({"uri": uri, "diagnostics": []}),
+                );
+            }
+            "textDocument/formatting" => self.document(uri).and_then(|content| {
+                format_toml_edits(content, self.opt)
+                    .map(|edits| to_lsp_edits(content, &edits))
+                    .map_err(|e| (REQUEST_FAILED, e))
+            }),
+            "textDocument/rangeFormatting" => self.document(uri).and_then(|content| {
+                format_range(content, self.opt, &to_offsets(content, &params["range"]))
+                    .map(|edits| to_lsp_edits(content, &edits))
+                    .map_err(|e| (REQUEST_FAILED, e))
+            }),
+            "textDocument/codeAction" => self
+                .document(uri)
+                .map(|content| code_actions(content, uri, &to_offsets(content, &params["range"]), self.opt)),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {method:?}"))),
+        };
+        // notifications carry no id and get no response
+        let Some(id) = message.get("id") else {
+            return Ok(());
+        };
+        self.send(&match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}),
+        })
+    }
+
+    fn document(&self, uri: &str) -> Result<&str, (i64, String)> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| (REQUEST_FAILED, format!("document {uri} is not open")))
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<(), String> {
+        let Ok(content) = self.document(uri) else {
+            return Ok(());
+        };
+        let diagnostics = diagnose(
+            content,
+            self.opt.min_supported_python,
+            self.opt.max_supported_python,
+            self.opt.migrate_poetry,
+        )
+        .iter()
+        .map(|d| {
+            json!({
+                "range": to_lsp_range(content, &(d.range.start().into()..d.range.end().into())),
+                "severity": match d.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                },
+                "code": d.code,
+                "source": "pyproject-fmt",
+                "message": d.message,
+            })
+        })
+        .collect::<Vec<Value>>();
+        self.notify(
+            "textDocument/publishDiagnostics",
+            &json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Result<(), String> {
+        self.send(&json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    fn send(&mut self, message: &Value) -> Result<(), String> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())
+            .and_then(|()| self.output.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The fixes that apply within the byte `range` of the document: normalizing a requirement, and adding the Python
+/// version classifiers formatting would generate.
+fn code_actions(content: &str, uri: &str, range: &Range<usize>, opt: &Settings) -> Value {
+    let root = parse(content).into_dom();
+    let touches = |at: &Range<usize>| at.start <= range.end && range.start <= at.end;
+    let mut actions = Vec::<Value>::new();
+    let mut requirements = vec![
+        lookup(&root, &["build-system", "requires"]),
+        lookup(&root, &["project", "dependencies"]),
+    ];
+    if let Some(Node::Table(extras)) = lookup(&root, &["project", "optional-dependencies"]) {
+        requirements.extend(extras.entries().read().iter().map(|(_, value)| Some(value.clone())));
+    }
+    for (value, item) in requirements.iter().flat_map(|node| strings(node.as_ref())) {
+        let at = to_range(&item);
+        let normalized = format_requirement_or_keep(&value, opt.keep_full_version);
+        if touches(&at) && normalized != value {
+            let edit = TextEdit {
+                range: at,
+                new_text: make_string_node(&normalized).to_string(),
+            };
+            actions.push(quick_fix(
+                &format!("Normalize requirement to {normalized:?}"),
+                uri,
+                content,
+                &edit,
+            ));
+        }
+    }
+    if let Some(edit) = add_classifiers(content, &root, opt).filter(|(at, _)| touches(at)) {
+        actions.push(quick_fix(
+            "Add missing Python version classifiers",
+            uri,
+            content,
+            &edit.1,
+        ));
+    }
+    Value::Array(actions)
+}
+
+/// The edit adding the Python version classifiers formatting would generate, with the range offering it: the
+/// classifiers array, or the project table header when there is none.
+#[allow(clippy::range_plus_one)]
+fn add_classifiers(content: &str, root: &Node, opt: &Settings) -> Option<(Range<usize>, TextEdit)> {
+    let formatted = parse(&format_toml(content, opt).ok()?).into_dom();
+    let existing = lookup(root, &["project", "classifiers"]);
+    let present = strings(existing.as_ref())
+        .into_iter()
+        .map(|(value, _)| value)
+        .collect::<Vec<String>>();
+    let missing = strings(lookup(&formatted, &["project", "classifiers"]).as_ref())
+        .into_iter()
+        .map(|(value, _)| value)
+        .filter(|value| value.starts_with("Programming Language :: Python :: 3") && !present.contains(value))
+        .map(|value| make_string_node(&value).to_string())
+        .collect::<Vec<String>>();
+    if missing.is_empty() {
+        return None;
+    }
+    if let Some(array) = existing.as_ref().and_then(Node::as_array) {
+        let at = to_range(existing.as_ref()?);
+        let items = array.items().read();
+        // after the last item, or right after the opening bracket of an empty array
+        let edit = items.last().map_or_else(
+            || TextEdit {
+                range: at.start + 1..at.start + 1,
+                new_text: missing.join(", "),
+            },
+            |last| TextEdit {
+                range: to_range(last).end..to_range(last).end,
+                new_text: format!(", {}", missing.join(", ")),
+            },
+        );
+        return Some((at, edit));
+    }
+    // without a classifiers key the entry goes right below the table header
+    let header = parse(content)
+        .into_syntax()
+        .children_with_tokens()
+        .find(|e| e.kind() == TABLE_HEADER && get_table_name(e) == "project")?;
+    let at = usize::from(header.text_range().start())..usize::from(header.text_range().end());
+    let line_end = content[at.end..].find('\n').map_or(content.len(), |i| at.end + i + 1);
+    let edit = TextEdit {
+        range: line_end..line_end,
+        new_text: format!("classifiers = [{}]\n", missing.join(", ")),
+    };
+    Some((at, edit))
+}
+
+fn quick_fix(title: &str, uri: &str, content: &str, edit: &TextEdit) -> Value {
+    json!({
+        "title": title,
+        "kind": "quickfix",
+        "edit": {"changes": {uri: to_lsp_edits(content, std::slice::from_ref(edit))}},
+    })
+}
+
+fn to_range(node: &Node) -> Range<usize> {
+    let range = range_of(node);
+    range.start().into()..range.end().into()
+}
+
+fn to_lsp_edits(content: &str, edits: &[TextEdit]) -> Value {
+    edits
+        .iter()
+        .map(|e| json!({"range": to_lsp_range(content, &e.range), "newText": e.new_text}))
+        .collect()
+}
+
+fn to_lsp_range(content: &str, range: &Range<usize>) -> Value {
+    json!({"start": to_position(content, range.start), "end": to_position(content, range.end)})
+}
+
+/// The zero based line and UTF-16 character of the byte `offset`, the default position encoding of the protocol.
+fn to_position(content: &str, offset: usize) -> Value {
+    let before = &content[..offset];
+    let line_start = before.rfind('\n').map_or(0, |at| at + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The byte offsets of the protocol `range`, positions past the end of a line or the document are clamped.
+fn to_offsets(content: &str, range: &Value) -> Range<usize> {
+    let offset = |position: &Value| {
+        let (line, character) = (
+            position["line"].as_u64().unwrap_or_default(),
+            position["character"].as_u64().unwrap_or_default(),
+        );
+        let Some(start) = (match line {
+            0 => Some(0),
+            _ => content
+                .match_indices('\n')
+                .nth(usize::try_from(line - 1).unwrap_or(usize::MAX))
+                .map(|(at, _)| at + 1),
+        }) else {
+            return content.len();
+        };
+        let text = content[start..].split_inclusive('\n').next().unwrap_or_default();
+        let (mut units, mut at) = (0, start);
+        for c in text.trim_end_matches(['\r', '\n']).chars() {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf16() as u64;
+            at += c.len_utf8();
+        }
+        at
+    };
+    offset(&range["start"])..offset(&range["end"])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use indoc::indoc;
+    use rstest::rstest;
+    use serde_json::{json, Value};
+
+    use crate::lsp::{read_message, serve, to_offsets, to_position};
+    use crate::Settings;
+
+    /// Run the server in process on the messages of the client, returns what it sent back.
+    fn session(messages: &[Value]) -> Vec<Value> {
+        raw_session(&messages.iter().map(Value::to_string).collect::<Vec<String>>())
+    }
+
+    /// Run the server in process on the message bodies of the client, which may be malformed.
+    fn raw_session(bodies: &[String]) -> Vec<Value> {
+        let mut input = String::new();
+        for body in bodies {
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+        }
+        let mut output = Vec::<u8>::new();
+        serve(Cursor::new(input), &mut output, &Settings::default()).unwrap();
+        let mut reader = BufReader::new(output.as_slice());
+        let mut sent = vec![];
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            sent.push(message.unwrap());
+        }
+        sent
+    }
+
+    fn open(text: &str) -> Value {
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": "file:///pyproject.toml", "languageId": "toml", "version": 1, "text": text},
+        }})
+    }
+
+    fn request(id: u64, method: &str, params: &Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({"start": {"line": start.0, "character": start.1}, "end": {"line": end.0, "character": end.1}})
+    }
+
+    #[rstest]
+    fn test_lifecycle() {
+        let sent = session(&[
+            request(1, "initialize", &json!({"capabilities": {}})),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            request(2, "textDocument/hover", &json!({})),
+            request(3, "shutdown", &Value::Null),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            request(4, "shutdown", &Value::Null),
+        ]);
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[0]["id"], 1);
+        assert_eq!(sent[0]["result"]["capabilities"]["documentFormattingProvider"], true);
+        assert_eq!(
+            sent[1]["error"],
+            json!({"code": -32601, "message": "unsupported method \"textDocument/hover\""})
+        );
+        assert_eq!(sent[2], json!({"jsonrpc": "2.0", "id": 3, "result": null}));
+    }
+
+    #[rstest]
+    fn test_malformed_message() {
+        let sent = raw_session(&[
+            String::from("{\"jsonrpc\": \"2.0\", \"id\": 1,"),
+            request(2, "shutdown", &Value::Null).to_string(),
+        ]);
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0]["id"], Value::Null);
+        assert_eq!(sent[0]["error"]["code"], -32700);
+        assert_eq!(sent[1], json!({"jsonrpc": "2.0", "id": 2, "result": null}));
+    }
+
+    #[rstest]
+    fn test_diagnostics() {
+        let sent = session(&[
+            open("[project]\nrequires-python = \">=4\"\n"),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": {"uri": "file:///pyproject.toml", "version": 2},
+                "contentChanges": [{"text": "[project]\n"}],
+            }}),
+        ]);
+        assert_eq!(
+            sent[0]["params"],
+            json!({"uri": "file:///pyproject.toml", "diagnostics": [{
+                "range": range((1, 18), (1, 23)),
+                "severity": 2,
+                "code": "PF103",
+                "source": "pyproject-fmt",
+                "message": "requires-python \">=4\" excludes every supported Python version (3.9 to 3.13)",
+            }]})
+        );
+        assert_eq!(sent[1]["params"]["diagnostics"], json!([]));
+    }
+
+    #[rstest]
+    fn test_formatting() {
+        let document = json!({"textDocument": {"uri": "file:///pyproject.toml"}});
+        let sent = session(&[
+            open("[tool.a]\na=1\n"),
+            request(1, "textDocument/formatting", &document),
+            request(
+                2,
+                "textDocument/formatting",
+                &json!({"textDocument": {"uri": "file:///other.toml"}}),
+            ),
+        ]);
+        assert_eq!(
+            sent[1]["result"],
+            json!([
+                {"range": range((1, 1), (1, 1)), "newText": " "},
+                {"range": range((1, 2), (1, 2)), "newText": " "},
+            ])
+        );
+        assert_eq!(sent[2]["error"]["code"], -32803);
+    }
+
+    #[rstest]
+    fn test_invalid_requirement() {
+        let document = json!({"textDocument": {"uri": "file:///pyproject.toml"}});
+        let sent = session(&[
+            open("[project]\nrequires-python = \">=3.13\"\ndependencies = [\"requests>=\"]\n"),
+            request(1, "textDocument/formatting", &document),
+            request(
+                2,
+                "textDocument/codeAction",
+                &json!({"textDocument": {"uri": "file:///pyproject.toml"}, "range": range((2, 17), (2, 17)), "context": {"diagnostics": []}}),
+            ),
+        ]);
+        // the requirement is kept as is, while the rest of the document still gets formatted
+        assert_eq!(
+            sent[1]["result"],
+            json!([
+                {
+                    "range": range((2, 0), (2, 0)),
+                    "newText": "classifiers = [ \"Programming Language :: Python :: 3 :: Only\", \
+                    \"Programming Language :: Python :: 3.13\" ]\n",
+                },
+                {"range": range((2, 16), (2, 16)), "newText": " "},
+                {"range": range((2, 28), (2, 28)), "newText": " "},
+            ])
+        );
+        assert_eq!(sent[2]["result"], json!([]));
+    }
+
+    #[rstest]
+    fn test_range_formatting() {
+        let sent = session(&[
+            open("[tool.a]\na=1\n\n[tool.b]\nb=1\n"),
+            request(
+                1,
+                "textDocument/rangeFormatting",
+                &json!({"textDocument": {"uri": "file:///pyproject.toml"}, "range": range((4, 0), (4, 0))}),
+            ),
+        ]);
+        assert_eq!(
+            sent[1]["result"],
+            json!([
+                {"range": range((4, 1), (4, 1)), "newText": " "},
+                {"range": range((4, 2), (4, 2)), "newText": " "},
+            ])
+        );
+    }
+
+    #[rstest]
+    #[case::normalize_requirement(
+        indoc ! {r#"
+        [project]
+        requires-python = ">=3.12"
+        classifiers = ["Programming Language :: Python :: 3 :: Only", "Programming Language :: Python :: 3.12", "Programming Language :: Python :: 3.13"]
+        dependencies = ["A>=1.0", "b"]
+        "#},
+        range((3, 17), (3, 17)),
+        json!([{
+            "title": "Normalize requirement to \"a>=1\"",
+            "kind": "quickfix",
+            "edit": {"changes": {"file:///pyproject.toml": [{"range": range((3, 16), (3, 24)), "newText": "\"a>=1\""}]}},
+        }])
+    )]
+    #[case::add_to_classifiers(
+        indoc ! {r#"
+        [project]
+        requires-python = ">=3.12"
+        classifiers = ["Private :: Do Not Upload"]
+        "#},
+        range((2, 20), (2, 20)),
+        json!([{
+            "title": "Add missing Python version classifiers",
+            "kind": "quickfix",
+            "edit": {"changes": {"file:///pyproject.toml": [{
+                "range": range((2, 41), (2, 41)),
+                "newText": ", \"Programming Language :: Python :: 3 :: Only\", \"Programming Language :: Python :: 3.12\", \
+                \"Programming Language :: Python :: 3.13\"",
+            }]}},
+        }])
+    )]
+    #[case::add_classifiers_key(
+        indoc ! {r#"
+        [project]
+        requires-python = ">=3.13"
+        "#},
+        range((0, 3), (0, 3)),
+        json!([{
+            "title": "Add missing Python version classifiers",
+            "kind": "quickfix",
+            "edit": {"changes": {"file:///pyproject.toml": [{
+                "range": range((1, 0), (1, 0)),
+                "newText": "classifiers = [\"Programming Language :: Python :: 3 :: Only\", \
+                \"Programming Language :: Python :: 3.13\"]\n",
+            }]}},
+        }])
+    )]
+    #[case::nothing_to_fix(
+        indoc ! {r#"
+        [project]
+        requires-python = ">=3.13"
+        dependencies = ["a>=1"]
+        "#},
+        range((2, 17), (2, 17)),
+        json!([])
+    )]
+    fn test_code_actions(#[case] text: &str, #[case] at: Value, #[case] expected: Value) {
+        let sent = session(&[
+            open(text),
+            request(
+                1,
+                "textDocument/codeAction",
+                &json!({"textDocument": {"uri": "file:///pyproject.toml"}, "range": at, "context": {"diagnostics": []}}),
+            ),
+        ]);
+        assert_eq!(sent[1]["result"], expected);
+    }
+
+    #[rstest]
+    #[case::start(0, (0, 0))]
+    #[case::second_line(4, (1, 1))]
+    #[case::after_wide_character(9, (1, 4))]
+    #[case::end(12, (2, 0))]
+    fn test_positions(#[case] offset: usize, #[case] position: (u64, u64)) {
+        let content = "ab\ncd\u{1f600}ef\n";
+        assert_eq!(
+            to_position(content, offset),
+            json!({"line": position.0, "character": position.1})
+        );
+        assert_eq!(to_offsets(content, &range(position, position)), offset..offset);
+    }
+
+    #[rstest]
+    fn test_offsets_clamped() {
+        assert_eq!(to_offsets("ab\ncd\n", &range((0, 9), (7, 0))), 2..6);
+    }
+}
@@ -1,58 +1,573 @@
+use std::collections::HashMap;
+use std::io::{stdin, stdout};
+use std::ops::Range;
 use std::string::String;
 
+use globset::Glob;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::PyModule;
 use pyo3::{pyclass, pyfunction, pymethods, pymodule, wrap_pyfunction, Bound, PyResult};
+use taplo::dom::Node;
 use taplo::formatter::{format_syntax, Options};
 use taplo::parser::parse;
 
-use crate::global::reorder_tables;
-use crate::helpers::table::Tables;
+use crate::check::{Normalization, Order};
+use crate::diagnostics::{diagnose, to_json, to_sarif, PyDiagnostic};
+use crate::edits::{apply, diff, line_range, selected_tables, TextEdit};
+use crate::global::{apply_table_format, reorder_tables};
+use crate::helpers::dom::{lookup, strings};
+use crate::helpers::table::{sort_table_array, Tables};
+use crate::validate::{describe, validate};
 
 mod build_system;
+mod check;
+mod cibuildwheel;
+mod coverage;
+mod diagnostics;
+mod edits;
+mod lsp;
+mod mypy;
+mod poetry;
 mod project;
+mod pytest;
 
 mod global;
+mod hatch;
 mod helpers;
 mod ruff;
+mod setuptools;
+mod tox;
+mod uv;
+mod validate;
 
 #[pyclass(frozen, get_all)]
+#[derive(Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Settings {
     column_width: usize,
     indent: usize,
     keep_full_version: bool,
     max_supported_python: (u8, u8),
     min_supported_python: (u8, u8),
+    table_format: String,
+    expand_tables: Vec<String>,
+    collapse_tables: Vec<String>,
+    table_order: Vec<String>,
+    table_order_mode: String,
+    sort_unknown_tools: bool,
+    table_array_sort_keys: HashMap<String, String>,
+    lenient: bool,
+    line_ending: String,
+    indent_style: String,
+    align_entries: bool,
+    align_comments: bool,
+    align_single_comments: bool,
+    array_trailing_comma: bool,
+    array_auto_expand: bool,
+    array_auto_collapse: bool,
+    compact_arrays: bool,
+    compact_inline_tables: bool,
+    compact_entries: bool,
+    indent_tables: bool,
+    indent_entries: bool,
+    inline_table_expand: bool,
+    trailing_newline: bool,
+    allowed_blank_lines: usize,
+    blank_lines_between_groups: usize,
+    blank_lines_between_sub_tables: Option<usize>,
+    keep_banner_comments: bool,
+    self_check: bool,
+    split_pytest_addopts: bool,
+    sort_pytest_filterwarnings: bool,
+    migrate_poetry: bool,
 }
 
 #[pymethods]
 impl Settings {
     #[new]
-    #[pyo3(signature = (*, column_width, indent, keep_full_version, max_supported_python, min_supported_python ))]
-    const fn new(
+    #[pyo3(signature = (
+        *,
+        column_width,
+        indent,
+        keep_full_version,
+        max_supported_python,
+        min_supported_python,
+        table_format = String::from("keep"),
+        expand_tables = vec![],
+        collapse_tables = vec![],
+        table_order = vec![],
+        table_order_mode = String::from("prepend"),
+        sort_unknown_tools = false,
+        table_array_sort_keys = HashMap::new(),
+        lenient = false,
+        line_ending = String::from("auto"),
+        indent_style = String::from("space"),
+        align_entries = false,
+        align_comments = true,
+        align_single_comments = true,
+        array_trailing_comma = true,
+        array_auto_expand = true,
+        array_auto_collapse = false,
+        compact_arrays = false,
+        compact_inline_tables = false,
+        compact_entries = false,
+        indent_tables = false,
+        indent_entries = false,
+        inline_table_expand = true,
+        trailing_newline = true,
+        allowed_blank_lines = 1,
+        blank_lines_between_groups = 1,
+        blank_lines_between_sub_tables = None,
+        keep_banner_comments = false,
+        self_check = false,
+        split_pytest_addopts = false,
+        sort_pytest_filterwarnings = false,
+        migrate_poetry = false,
+    ))]
+    #[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)]
+    fn new(
         column_width: usize,
         indent: usize,
         keep_full_version: bool,
         max_supported_python: (u8, u8),
         min_supported_python: (u8, u8),
-    ) -> Self {
-        Self {
+        table_format: String,
+        expand_tables: Vec<String>,
+        collapse_tables: Vec<String>,
+        table_order: Vec<String>,
+        table_order_mode: String,
+        sort_unknown_tools: bool,
+        table_array_sort_keys: HashMap<String, String>,
+        lenient: bool,
+        line_ending: String,
+        indent_style: String,
+        align_entries: bool,
+        align_comments: bool,
+        align_single_comments: bool,
+        array_trailing_comma: bool,
+        array_auto_expand: bool,
+        array_auto_collapse: bool,
+        compact_arrays: bool,
+        compact_inline_tables: bool,
+        compact_entries: bool,
+        indent_tables: bool,
+        indent_entries: bool,
+        inline_table_expand: bool,
+        trailing_newline: bool,
+        allowed_blank_lines: usize,
+        blank_lines_between_groups: usize,
+        blank_lines_between_sub_tables: Option<usize>,
+        keep_banner_comments: bool,
+        self_check: bool,
+        split_pytest_addopts: bool,
+        sort_pytest_filterwarnings: bool,
+        migrate_poetry: bool,
+    ) -> PyResult<Self> {
+        let settings = Self {
             column_width,
             indent,
             keep_full_version,
             max_supported_python,
             min_supported_python,
+            table_format,
+            expand_tables,
+            collapse_tables,
+            table_order,
+            table_order_mode,
+            sort_unknown_tools,
+            table_array_sort_keys,
+            lenient,
+            line_ending,
+            indent_style,
+            align_entries,
+            align_comments,
+            align_single_comments,
+            array_trailing_comma,
+            array_auto_expand,
+            array_auto_collapse,
+            compact_arrays,
+            compact_inline_tables,
+            compact_entries,
+            indent_tables,
+            indent_entries,
+            inline_table_expand,
+            trailing_newline,
+            allowed_blank_lines,
+            blank_lines_between_groups,
+            blank_lines_between_sub_tables,
+            keep_banner_comments,
+            self_check,
+            split_pytest_addopts,
+            sort_pytest_filterwarnings,
+            migrate_poetry,
+        };
+        settings.validate().map_err(PyValueError::new_err)?;
+        Ok(settings)
+    }
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), String> {
+        let one_of = |name: &str, value: &str, allowed: &[&str]| {
+            if allowed.contains(&value) {
+                Ok(())
+            } else {
+                Err(format!("{name} must be one of {}, got {value:?}", allowed.join(", ")))
+            }
+        };
+        one_of("table_format", &self.table_format, &["keep", "short", "long"])?;
+        one_of(
+            "table_order_mode",
+            &self.table_order_mode,
+            &["prepend", "append", "override"],
+        )?;
+        one_of("line_ending", &self.line_ending, &["auto", "lf", "crlf"])?;
+        one_of("indent_style", &self.indent_style, &["space", "tab"])?;
+        if self.column_width == 0 {
+            return Err(String::from("column_width must be positive"));
+        }
+        if self.indent == 0 && self.indent_style == "space" {
+            return Err(String::from("indent must be positive"));
+        }
+        if let Some(Err(err)) = self.table_order.iter().map(|p| Glob::new(p)).find(Result::is_err) {
+            return Err(format!("invalid table_order pattern: {err}"));
+        }
+        if self.allowed_blank_lines < self.blank_lines() {
+            return Err(format!(
+                "allowed_blank_lines must be at least the blank lines between tables ({}), got {}",
+                self.blank_lines(),
+                self.allowed_blank_lines
+            ));
+        }
+        Ok(())
+    }
+
+    /// The most blank lines the table separation asks for, the formatter must not collapse these.
+    fn blank_lines(&self) -> usize {
+        self.blank_lines_between_groups
+            .max(self.blank_lines_between_sub_tables.unwrap_or_default())
+    }
+
+    fn indent_string(&self) -> String {
+        if self.indent_style == "tab" {
+            String::from("\t")
+        } else {
+            " ".repeat(self.indent)
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            column_width: 120,
+            indent: 2,
+            keep_full_version: false,
+            max_supported_python: (3, 13),
+            min_supported_python: (3, 9),
+            table_format: String::from("keep"),
+            expand_tables: vec![],
+            collapse_tables: vec![],
+            table_order: vec![],
+            table_order_mode: String::from("prepend"),
+            sort_unknown_tools: false,
+            table_array_sort_keys: HashMap::new(),
+            lenient: false,
+            line_ending: String::from("auto"),
+            indent_style: String::from("space"),
+            align_entries: false,         // do not align by =
+            align_comments: true,         // align inline comments
+            align_single_comments: true,  // align comments after entries
+            array_trailing_comma: true,   // ensure arrays finish with trailing comma
+            array_auto_expand: true,      // arrays go to multi line when too long
+            array_auto_collapse: false,   // do not collapse for easier diffs
+            compact_arrays: false,        // leave whitespace
+            compact_inline_tables: false, // leave whitespace
+            compact_entries: false,       // leave whitespace
+            indent_tables: false,
+            indent_entries: false,
+            inline_table_expand: true,
+            trailing_newline: true,
+            allowed_blank_lines: 1, // one blank line to separate
+            blank_lines_between_groups: 1,
+            blank_lines_between_sub_tables: None, // keep as written
+            keep_banner_comments: false,
+            self_check: false,
+            split_pytest_addopts: false,
+            sort_pytest_filterwarnings: false, // later filters win, the order matters
+            migrate_poetry: false,
         }
     }
 }
 
 /// Format toml file
+///
+/// # Errors
+///
+/// Will return `PyErr` (a `ValueError`) if the file cannot be formatted safely, see [`format_toml`].
+#[pyfunction]
+#[pyo3(name = "format_toml")]
+#[cfg(not(tarpaulin_include))]
+pub fn py_format_toml(content: &str, opt: &Settings) -> PyResult<String> {
+    format_toml(content, opt).map_err(PyValueError::new_err)
+}
+
+/// Problems found in the toml file
+#[pyfunction]
+#[pyo3(name = "diagnose")]
+#[cfg(not(tarpaulin_include))]
 #[must_use]
+pub fn py_diagnose(content: &str, opt: &Settings) -> Vec<PyDiagnostic> {
+    diagnose(
+        content,
+        opt.min_supported_python,
+        opt.max_supported_python,
+        opt.migrate_poetry,
+    )
+    .iter()
+    .map(|d| PyDiagnostic::new(d, content))
+    .collect()
+}
+
+/// Problems found in the toml file as a JSON array or a SARIF log
+///
+/// # Errors
+///
+/// Will return `PyErr` (a `ValueError`) if the output format is not `json` or `sarif`.
+#[pyfunction]
+#[pyo3(name = "diagnostics_report", signature = (content, opt, *, output_format = "json", path = "pyproject.toml"))]
+#[cfg(not(tarpaulin_include))]
+pub fn py_diagnostics_report(content: &str, opt: &Settings, output_format: &str, path: &str) -> PyResult<String> {
+    let found = diagnose(
+        content,
+        opt.min_supported_python,
+        opt.max_supported_python,
+        opt.migrate_poetry,
+    );
+    match output_format {
+        "json" => Ok(to_json(&found, content)),
+        "sarif" => Ok(to_sarif(&found, content, path)),
+        _ => Err(PyValueError::new_err(format!(
+            "output_format must be one of json, sarif, got {output_format:?}"
+        ))),
+    }
+}
+
+/// Format toml file, returns the edits to apply as `(start, end, replacement)` byte offsets
+///
+/// # Errors
+///
+/// Will return `PyErr` (a `ValueError`) if the file cannot be formatted safely, see [`format_toml`].
 #[pyfunction]
-pub fn format_toml(content: &str, opt: &Settings) -> String {
+#[pyo3(name = "format_toml_edits")]
+#[cfg(not(tarpaulin_include))]
+pub fn py_format_toml_edits(content: &str, opt: &Settings) -> PyResult<Vec<(usize, usize, String)>> {
+    let edits = format_toml_edits(content, opt).map_err(PyValueError::new_err)?;
+    Ok(edits
+        .into_iter()
+        .map(|e| (e.range.start, e.range.end, e.new_text))
+        .collect())
+}
+
+/// Format the tables of the toml file within the byte offsets `start` to `end` (or the one based, inclusive lines when
+/// `lines` is set), returns the edits to apply as `(start, end, replacement)` byte offsets
+///
+/// # Errors
+///
+/// Will return `PyErr` (a `ValueError`) if the file cannot be formatted safely, see [`format_toml`].
+#[pyfunction]
+#[pyo3(name = "format_range", signature = (content, opt, start, end, *, lines = false))]
+#[cfg(not(tarpaulin_include))]
+pub fn py_format_range(
+    content: &str,
+    opt: &Settings,
+    start: usize,
+    end: usize,
+    lines: bool,
+) -> PyResult<Vec<(usize, usize, String)>> {
+    let range = if lines {
+        line_range(content, start, end)
+    } else {
+        start..end
+    };
+    let edits = format_range(content, opt, &range).map_err(PyValueError::new_err)?;
+    Ok(edits
+        .into_iter()
+        .map(|e| (e.range.start, e.range.end, e.new_text))
+        .collect())
+}
+
+/// Serve the Language Server Protocol over the standard streams until the client exits
+///
+/// # Errors
+///
+/// Will return `PyErr` (a `ValueError`) if the client sends a malformed message or the streams fail.
+#[pyfunction]
+#[pyo3(name = "serve_lsp")]
+#[cfg(not(tarpaulin_include))]
+pub fn py_serve_lsp(opt: &Settings) -> PyResult<()> {
+    lsp::serve(stdin().lock(), stdout().lock(), opt).map_err(PyValueError::new_err)
+}
+
+/// Format toml file
+///
+/// # Errors
+///
+/// Will return an error message if the settings (including the table order set in the `[tool.pyproject-fmt]` table of
+/// the file) are invalid, if the file defines a key or table more than once, unless the settings ask for leniency, or
+/// if the formatted document would hold different data than the original one. In self-check mode also if formatting
+/// the output again would change it.
+pub fn format_toml(content: &str, opt: &Settings) -> Result<String, String> {
+    // the byte order mark is not part of the document, but it is kept for the output
+    let (bom, content) = content
+        .strip_prefix('\u{feff}')
+        .map_or(("", content), |rest| ("\u{feff}", rest));
+    let opt = &prepare(content, opt)?;
+    let formatted = format_document(content, opt, true);
+    // a document with conflicting definitions has no single meaning to keep, those are merged on request
+    if validate(content).is_empty() {
+        check::ensure_equivalent(content, &formatted, opt, NORMALIZATIONS)?;
+    }
+    if opt.self_check {
+        check::ensure_stable(&formatted, &format_document(&formatted, opt, true))?;
+    }
+    Ok(format!("{bom}{formatted}"))
+}
+
+/// Format toml file, returning the smallest set of non-overlapping edits turning the file into the formatted one.
+///
+/// # Errors
+///
+/// Will return an error message for the same reasons as [`format_toml`].
+pub fn format_toml_edits(content: &str, opt: &Settings) -> Result<Vec<TextEdit>, String> {
+    let formatted = format_toml(content, opt)?;
+    let shift = usize::from(content.starts_with('\u{feff}')) * '\u{feff}'.len_utf8();
+    Ok(diff(&content[shift..], &formatted[shift..], shift))
+}
+
+/// Format the tables intersecting the byte `range` of the file (the table under the position when empty), leaving the
+/// rest of the document, including the order of tables, as is.
+///
+/// # Errors
+///
+/// Will return an error message for the same reasons as [`format_toml`], or when formatting the selection needs
+/// changes to the tables around it (e.g. folding a selected sub-table into its parent).
+pub fn format_range(content: &str, opt: &Settings, range: &Range<usize>) -> Result<Vec<TextEdit>, String> {
+    let (bom, content) = content
+        .strip_prefix('\u{feff}')
+        .map_or(("", content), |rest| ("\u{feff}", rest));
+    let opt = &prepare(content, opt)?;
+    let shift = bom.len();
+    let Some(block) = selected_tables(
+        content,
+        &(range.start.saturating_sub(shift)..range.end.saturating_sub(shift)),
+    ) else {
+        return Ok(vec![]);
+    };
+    // the fixers see the whole document (e.g. the parent table of a selected sub-table), the tables keep their order
+    let formatted = format_document(content, opt, false);
+    let entangled =
+        "the selection cannot be formatted without changing the tables around it, format the whole document";
+    let mut edits = Vec::<TextEdit>::new();
+    for edit in diff(content, &formatted, 0) {
+        if block.start <= edit.range.start && edit.range.end <= block.end {
+            edits.push(edit);
+        } else if edit.range.start < block.end && block.start < edit.range.end {
+            return Err(String::from(entangled));
+        }
+    }
+    if validate(content).is_empty() {
+        let edited = apply(content, &edits);
+        if !validate(&edited).is_empty() {
+            return Err(String::from(entangled));
+        }
+        check::ensure_equivalent(content, &edited, opt, NORMALIZATIONS).map_err(|e| format!("{entangled}\n{e}"))?;
+    }
+    Ok(edits
+        .into_iter()
+        .map(|e| TextEdit {
+            range: e.range.start + shift..e.range.end + shift,
+            new_text: e.new_text,
+        })
+        .collect())
+}
+
+/// The rewrites the fixers make on purpose, by fixer, which the equivalence check ignores.
+const NORMALIZATIONS: &[&[Normalization]] = &[
+    // asked for explicitly, so these take precedence over the order the fixers keep
+    TABLE_ARRAY_SORT,
+    build_system::NORMALIZATIONS,
+    project::NORMALIZATIONS,
+    ruff::NORMALIZATIONS,
+    mypy::NORMALIZATIONS,
+    pytest::NORMALIZATIONS,
+    coverage::NORMALIZATIONS,
+    tox::NORMALIZATIONS,
+    poetry::NORMALIZATIONS,
+    uv::NORMALIZATIONS,
+    hatch::NORMALIZATIONS,
+    setuptools::NORMALIZATIONS,
+    cibuildwheel::NORMALIZATIONS,
+];
+
+/// The arrays of tables sorted on request.
+const TABLE_ARRAY_SORT: &[Normalization] = &[Normalization {
+    applies: |path, opt| opt.table_array_sort_keys.contains_key(path),
+    order: Order::Any,
+    ..Normalization::NONE
+}];
+
+/// The settings for the document: the given ones, with the table order (`table_order`, `table_order_mode` and
+/// `sort_unknown_tools`) taken from its `[tool.pyproject-fmt]` table where set there. Fails on invalid settings and,
+/// unless these ask for leniency, on a document defining a key or table more than once; done once per request, the
+/// formatting passes rely on it.
+fn prepare(content: &str, opt: &Settings) -> Result<Settings, String> {
+    let mut opt = opt.clone();
+    if let Some(Node::Table(config)) = lookup(&parse(content).into_dom(), &["tool", "pyproject-fmt"]) {
+        for (key, value) in config.entries().read().iter() {
+            let invalid = |kind: &str| format!("tool.pyproject-fmt.{} must be {kind}", key.value());
+            match key.value() {
+                "table_order" => {
+                    let patterns = strings(Some(value));
+                    if value.as_array().map(|a| a.items().read().len()) != Some(patterns.len()) {
+                        return Err(invalid("an array of strings"));
+                    }
+                    opt.table_order = patterns.into_iter().map(|(pattern, _)| pattern).collect();
+                }
+                "table_order_mode" => {
+                    opt.table_order_mode = String::from(value.as_str().ok_or_else(|| invalid("a string"))?.value());
+                }
+                "sort_unknown_tools" => {
+                    opt.sort_unknown_tools = value.as_bool().ok_or_else(|| invalid("a boolean"))?.value();
+                }
+                _ => {}
+            }
+        }
+    }
+    opt.validate()?;
+    if !opt.lenient {
+        let problems = validate(content);
+        if !problems.is_empty() {
+            return Err(describe(&problems, content));
+        }
+    }
+    Ok(opt)
+}
+
+fn format_document(content: &str, opt: &Settings, reorder: bool) -> String {
+    let crlf = match opt.line_ending.as_str() {
+        "crlf" => true,
+        "lf" => false,
+        _ => uses_crlf(content),
+    };
     let root_ast = parse(content).into_syntax().clone_for_update();
-    let mut tables = Tables::from_ast(&root_ast);
+    let mut tables = Tables::from_ast(&root_ast, opt.keep_banner_comments);
 
     build_system::fix(&tables, opt.keep_full_version);
+    // the migration moves keys between tables, so it is only done for the whole document
+    if opt.migrate_poetry && reorder {
+        poetry::migrate(content, &mut tables);
+    }
     project::fix(
         &mut tables,
         opt.keep_full_version,
@@ -60,32 +575,63 @@ pub fn format_toml(content: &str, opt: &Settings) -> String {
         opt.min_supported_python,
     );
     ruff::fix(&mut tables);
-    reorder_tables(&root_ast, &tables);
+    mypy::fix(&tables);
+    pytest::fix(&tables, opt.split_pytest_addopts, opt.sort_pytest_filterwarnings);
+    coverage::fix(&mut tables);
+    tox::fix(&mut tables, opt.keep_full_version);
+    poetry::fix(&mut tables);
+    uv::fix(&tables, opt.keep_full_version);
+    hatch::fix(&mut tables, opt.keep_full_version);
+    setuptools::fix(&mut tables);
+    cibuildwheel::fix(&mut tables, opt.keep_full_version);
+    for (name, key) in &opt.table_array_sort_keys {
+        sort_table_array(&tables, name, key);
+    }
+    apply_table_format(&mut tables, &opt.table_format, &opt.expand_tables, &opt.collapse_tables);
+    if reorder {
+        reorder_tables(
+            &root_ast,
+            &tables,
+            &opt.table_order,
+            &opt.table_order_mode,
+            opt.sort_unknown_tools,
+            opt.blank_lines_between_groups,
+            opt.blank_lines_between_sub_tables,
+        );
+    } else {
+        tables.write_back(&root_ast);
+    }
 
     let options = Options {
-        align_entries: false,         // do not align by =
-        align_comments: true,         // align inline comments
-        align_single_comments: true,  // align comments after entries
-        array_trailing_comma: true,   // ensure arrays finish with trailing comma
-        array_auto_expand: true,      // arrays go to multi line when too long
-        array_auto_collapse: false,   // do not collapse for easier diffs
-        compact_arrays: false,        // leave whitespace
-        compact_inline_tables: false, // leave whitespace
-        compact_entries: false,       // leave whitespace
+        align_entries: opt.align_entries,
+        align_comments: opt.align_comments,
+        align_single_comments: opt.align_single_comments,
+        array_trailing_comma: opt.array_trailing_comma,
+        array_auto_expand: opt.array_auto_expand,
+        array_auto_collapse: opt.array_auto_collapse,
+        compact_arrays: opt.compact_arrays,
+        compact_inline_tables: opt.compact_inline_tables,
+        compact_entries: opt.compact_entries,
         column_width: opt.column_width,
-        indent_tables: false,
-        indent_entries: false,
-        inline_table_expand: true,
-        trailing_newline: true,
-        allowed_blank_lines: 1, // one blank line to separate
-        indent_string: " ".repeat(opt.indent),
+        indent_tables: opt.indent_tables,
+        indent_entries: opt.indent_entries,
+        inline_table_expand: opt.inline_table_expand,
+        trailing_newline: opt.trailing_newline,
+        allowed_blank_lines: opt.allowed_blank_lines,
+        indent_string: opt.indent_string(),
         reorder_keys: false,   // respect custom order
         reorder_arrays: false, // for natural sorting we need to this ourselves
-        crlf: false,
+        crlf,
     };
     format_syntax(root_ast, options)
 }
 
+/// True if most of the line endings within the content are CRLF.
+fn uses_crlf(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    crlf > content.matches('\n').count() - crlf
+}
+
 /// # Errors
 ///
 /// Will return `PyErr` if an error is raised during formatting.
@@ -93,20 +639,30 @@ pub fn format_toml(content: &str, opt: &Settings) -> String {
 #[pyo3(name = "_lib")]
 #[cfg(not(tarpaulin_include))]
 pub fn _lib(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(format_toml, m)?)?;
+    m.add_function(wrap_pyfunction!(py_format_toml, m)?)?;
+    m.add_function(wrap_pyfunction!(py_format_toml_edits, m)?)?;
+    m.add_function(wrap_pyfunction!(py_format_range, m)?)?;
+    m.add_function(wrap_pyfunction!(py_diagnose, m)?)?;
+    m.add_function(wrap_pyfunction!(py_diagnostics_report, m)?)?;
+    m.add_function(wrap_pyfunction!(py_serve_lsp, m)?)?;
     m.add_class::<Settings>()?;
+    m.add_class::<PyDiagnostic>()?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::fs::read_to_string;
+    use std::ops::Range;
     use std::path::{Path, PathBuf};
 
     use indoc::indoc;
     use rstest::{fixture, rstest};
 
-    use crate::{format_toml, Settings};
+    use crate::diagnostics::diagnose;
+    use crate::edits::{apply, TextEdit};
+    use crate::{format_range, format_toml, format_toml_edits, Settings};
 
     #[rstest]
     #[case::simple(
@@ -200,13 +756,10 @@ mod tests {
     ]
 
     [tool.coverage]
+    run.a = 3
+    paths.a = 1
+    report.a = 2
     a = 0
-    [tool.coverage.report]
-    a = 2
-    [tool.coverage.paths]
-    a = 1
-    [tool.coverage.run]
-    a = 3
     "#},
         2,
         true,
@@ -285,13 +838,136 @@ mod tests {
             keep_full_version,
             max_supported_python,
             min_supported_python: (3, 9),
+            ..Settings::default()
         };
-        let got = format_toml(start, &settings);
+        let got = format_toml(start, &settings).unwrap();
         assert_eq!(got, expected);
-        let second = format_toml(got.as_str(), &settings);
+        let edits = format_toml_edits(start, &settings).unwrap();
+        assert_eq!(apply(start, &edits), got);
+        let second = format_toml(got.as_str(), &settings).unwrap();
         assert_eq!(second, got);
     }
 
+    #[rstest]
+    fn test_self_check() {
+        let start = indoc! {r#"
+        [project]
+        name = "A"
+        requires-python = ">=3.12"
+        classifiers = ["Programming Language :: Python :: 3.8"]
+        dependencies = ["b>=1.0.0", "a"]
+        urls.docs = "https://a"
+        [build-system]
+        requires = ["hatchling"]
+        "#};
+        let settings = Settings {
+            self_check: true,
+            ..Settings::default()
+        };
+        let expected = format_toml(start, &Settings::default()).unwrap();
+        assert_eq!(format_toml(start, &settings), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::cursor_in_project(
+        40..40,
+        vec![
+            TextEdit { range: 37..37, new_text: String::from(indoc! {r#"
+            classifiers = [
+              "Programming Language :: Python :: 3 :: Only",
+              "Programming Language :: Python :: 3.9",
+              "Programming Language :: Python :: 3.10",
+              "Programming Language :: Python :: 3.11",
+              "Programming Language :: Python :: 3.12",
+              "Programming Language :: Python :: 3.13",
+            ]
+            "#}) },
+            TextEdit { range: 53..62, new_text: String::new() },
+            TextEdit { range: 66..66, new_text: String::from(", \"b>=1\" ") },
+        ]
+    )]
+    #[case::cursor_in_tool(
+        10..12,
+        vec![
+            TextEdit { range: 10..10, new_text: String::from(" ") },
+            TextEdit { range: 11..11, new_text: String::from(" ") },
+            TextEdit { range: 12..12, new_text: String::from(" ") },
+            TextEdit { range: 13..13, new_text: String::from(" ") },
+        ]
+    )]
+    #[case::outside_of_tables(200..210, vec![])]
+    fn test_format_range(#[case] range: Range<usize>, #[case] expected: Vec<TextEdit>) {
+        let start = indoc! {r#"
+        [tool.a]
+        a=[1]
+
+        [project]
+        name = "a"
+        dependencies = ["b>=1.0", "a"]
+
+        [build-system]
+        requires = []
+        "#};
+        assert_eq!(format_range(start, &Settings::default(), &range), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::sub_table_selected(50..50)]
+    #[case::parent_selected(5..5)]
+    fn test_format_range_parent_outside(#[case] range: Range<usize>) {
+        let start = indoc! {r#"
+        [project]
+        name = "a"
+
+        [tool.a]
+        a = 1
+
+        [project.urls]
+        docs = "https://a"
+        "#};
+        let settings = Settings {
+            table_format: String::from("short"),
+            ..Settings::default()
+        };
+        // folding the sub-table into its parent changes a table outside of the selection
+        let got = format_range(start, &settings, &range);
+        assert!(got.is_err_and(|e| e.starts_with("the selection cannot be formatted")));
+    }
+
+    #[rstest]
+    #[case::plain("", indoc! {r#"
+        [tool.a]
+        a=[1]  # one
+
+        [project]
+        name = "a"
+        "#})]
+    #[case::byte_order_mark("\u{feff}", indoc! {r#"
+        [tool.a]
+        a=[1]  # one
+
+        [project]
+        name = "a"
+        "#})]
+    #[case::insert_after_header(
+        "",
+        indoc! {r#"
+        [project.urls]
+        x = "u"
+        "#}
+    )]
+    fn test_format_toml_edits(#[case] bom: &str, #[case] content: &str) {
+        let start = format!("{bom}{content}");
+        let opt = Settings::default();
+        let edits = format_toml_edits(&start, &opt).unwrap();
+        assert!(edits.windows(2).all(|w| w[0].range.end < w[1].range.start));
+        let mut result = start.clone();
+        for edit in edits.iter().rev() {
+            result.replace_range(edit.range.clone(), &edit.new_text);
+        }
+        assert_eq!(result, format_toml(&start, &opt).unwrap());
+    }
+
     #[fixture]
     fn data() -> PathBuf {
         Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -309,11 +985,12 @@ mod tests {
             keep_full_version: false,
             max_supported_python: (3, 9),
             min_supported_python: (3, 9),
+            ..Settings::default()
         };
-        let got = format_toml(start.as_str(), &settings);
+        let got = format_toml(start.as_str(), &settings).unwrap();
         let expected = read_to_string(data.join("ruff-order.expected.toml")).unwrap();
         assert_eq!(got, expected);
-        let second = format_toml(got.as_str(), &settings);
+        let second = format_toml(got.as_str(), &settings).unwrap();
         assert_eq!(second, got);
     }
 
@@ -338,8 +1015,9 @@ mod tests {
             keep_full_version: false,
             max_supported_python: (3, 13),
             min_supported_python: (3, 13),
+            ..Settings::default()
         };
-        let got = format_toml(start, &settings);
+        let got = format_toml(start, &settings).unwrap();
         let expected = indoc! {r#"
         [build-system]
         build-backend = "backend"
@@ -356,7 +1034,364 @@ mod tests {
         ]
         "#};
         assert_eq!(got, expected);
-        let second = format_toml(got.as_str(), &settings);
+        let second = format_toml(got.as_str(), &settings).unwrap();
+        assert_eq!(second, got);
+    }
+
+    #[rstest]
+    #[case::sorted(
+        indoc ! {r#"
+        [[tool.uv.index]]
+        url = "https://b.example.com"
+        name = "beta"
+        [tool.uv.index.extra]
+        a = 1
+        [[tool.uv.index]]
+        name = "alpha"
+        default = true
+        [[tool.uv.index]]
+        url = "https://c.example.com"
+        [[tool.uv.index]]
+        name = "Gamma"
+        "#},
+        indoc ! {r#"
+        [[tool.uv.index]]
+        name = "alpha"
+        default = true
+
+        [[tool.uv.index]]
+        name = "beta"
+        url = "https://b.example.com"
+        [tool.uv.index.extra]
+        a = 1
+
+        [[tool.uv.index]]
+        name = "Gamma"
+
+        [[tool.uv.index]]
+        url = "https://c.example.com"
+        "#},
+        &[("tool.uv.index", "name")],
+    )]
+    #[case::not_requested(
+        indoc ! {r#"
+        [[tool.towncrier.type]]
+        name = "b"
+        [tool.towncrier.type.sub]
+        a = 1
+        [tool.mypy]
+        strict = true
+        [[tool.towncrier.type]]
+        name = "a"
+        "#},
+        indoc ! {r#"
+        [[tool.towncrier.type]]
+        name = "b"
+        [tool.towncrier.type.sub]
+        a = 1
+
+        [[tool.towncrier.type]]
+        name = "a"
+
+        [tool.mypy]
+        strict = true
+        "#},
+        &[],
+    )]
+    fn test_table_array_sort(#[case] start: &str, #[case] expected: &str, #[case] sort_keys: &[(&str, &str)]) {
+        let settings = Settings {
+            column_width: 120,
+            indent: 2,
+            keep_full_version: false,
+            max_supported_python: (3, 13),
+            min_supported_python: (3, 13),
+            table_array_sort_keys: sort_keys
+                .iter()
+                .map(|(k, v)| (String::from(*k), String::from(*v)))
+                .collect::<HashMap<String, String>>(),
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).unwrap();
+        assert_eq!(got, expected);
+        let edits = format_toml_edits(start, &settings).unwrap();
+        assert_eq!(apply(start, &edits), got);
+        let second = format_toml(got.as_str(), &settings).unwrap();
+        assert_eq!(second, got);
+    }
+
+    #[rstest]
+    #[case::strict(false, None)]
+    #[case::lenient(
+        true,
+        Some(indoc ! {r#"
+        [build-system]
+        build-backend = "b"
+        requires = [ "a" ]
+        "#})
+    )]
+    fn test_duplicate_table(#[case] lenient: bool, #[case] expected: Option<&str>) {
+        let start = indoc! {r#"
+        [build-system]
+        requires = ["a"]
+        [build-system]
+        build-backend = "b"
+        "#};
+        let settings = Settings {
+            column_width: 120,
+            indent: 2,
+            keep_full_version: false,
+            max_supported_python: (3, 13),
+            min_supported_python: (3, 13),
+            lenient,
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).ok();
+        assert_eq!(got.as_deref(), expected);
+    }
+
+    #[test]
+    fn test_duplicate_sub_table_expanded() {
+        let start = indoc! {r"
+        [tool.demo]
+        a.x = 1
+        [tool.demo.a]
+        y = 2
+        [tool.demo.a]
+        z = 3
+        "};
+        let settings = Settings {
+            column_width: 120,
+            indent: 2,
+            table_format: String::from("long"),
+            max_supported_python: (3, 13),
+            min_supported_python: (3, 13),
+            lenient: true,
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).unwrap();
+        let expected = indoc! {r"
+        [tool.demo]
+        [tool.demo.a]
+        y = 2
+        z = 3
+        x = 1
+        "};
+        assert_eq!(got, expected);
+    }
+
+    #[rstest]
+    #[case::lf(
+        "a = 1\n[tool.black]\nline-length = 120\n",
+        "auto",
+        "a = 1\n\n[tool.black]\nline-length = 120\n"
+    )]
+    #[case::crlf(
+        "a = 1\r\n[tool.black]\r\nline-length = 120\r\n",
+        "auto",
+        "a = 1\r\n\r\n[tool.black]\r\nline-length = 120\r\n"
+    )]
+    #[case::crlf_dominant(
+        "a = 1\r\n[tool.black]\nline-length = 120\r\n",
+        "auto",
+        "a = 1\r\n\r\n[tool.black]\r\nline-length = 120\r\n"
+    )]
+    #[case::force_lf(
+        "a = 1\r\n[tool.black]\r\nline-length = 120\r\n",
+        "lf",
+        "a = 1\n\n[tool.black]\nline-length = 120\n"
+    )]
+    #[case::force_crlf(
+        "a = 1\n[tool.black]\nline-length = 120\n",
+        "crlf",
+        "a = 1\r\n\r\n[tool.black]\r\nline-length = 120\r\n"
+    )]
+    #[case::bom(
+        "\u{feff}a = 1\r\n[tool.black]\r\nline-length = 120\r\n",
+        "auto",
+        "\u{feff}a = 1\r\n\r\n[tool.black]\r\nline-length = 120\r\n"
+    )]
+    fn test_line_ending(#[case] start: &str, #[case] line_ending: &str, #[case] expected: &str) {
+        let settings = Settings {
+            column_width: 120,
+            indent: 2,
+            keep_full_version: false,
+            max_supported_python: (3, 13),
+            min_supported_python: (3, 13),
+            line_ending: String::from(line_ending),
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).unwrap();
+        assert_eq!(got, expected);
+        let edits = format_toml_edits(start, &settings).unwrap();
+        assert_eq!(apply(start, &edits), got);
+        let second = format_toml(got.as_str(), &settings).unwrap();
+        assert_eq!(second, got);
+    }
+
+    #[rstest]
+    #[case::from_document(
+        indoc ! {r#"
+        [tool.mypy]
+        strict = true
+        [tool.zed]
+        a = 1
+        [tool.pyproject-fmt]
+        table_order = ["tool.pyproject-fmt", "tool.y*"]
+        sort_unknown_tools = true
+        [tool.yapf]
+        b = 2
+        [tool.ruff]
+        c = 3
+        "#},
+        Ok(indoc ! {r#"
+        [tool.pyproject-fmt]
+        table_order = [ "tool.pyproject-fmt", "tool.y*" ]
+        sort_unknown_tools = true
+
+        [tool.yapf]
+        b = 2
+
+        [tool.ruff]
+        c = 3
+
+        [tool.mypy]
+        strict = true
+
+        [tool.zed]
+        a = 1
+        "#})
+    )]
+    #[case::bad_mode(
+        indoc ! {r#"
+        [tool.pyproject-fmt]
+        table_order_mode = "middle"
+        "#},
+        Err(String::from("table_order_mode must be one of prepend, append, override, got \"middle\""))
+    )]
+    #[case::bad_type(
+        indoc ! {r#"
+        [tool.pyproject-fmt]
+        table_order = "tool.a"
+        "#},
+        Err(String::from("tool.pyproject-fmt.table_order must be an array of strings"))
+    )]
+    fn test_table_order_in_document(#[case] start: &str, #[case] expected: Result<&str, String>) {
+        let got = format_toml(start, &Settings::default());
+        assert_eq!(got.as_deref(), expected.as_deref());
+    }
+
+    #[rstest]
+    fn test_formatter_options() {
+        let start = indoc! {r#"
+        [tool.black]
+        line-length = 120
+        target-version = [
+          "py39",
+        ]
+        [tool.black.sub]
+        a = ["b",    "c"]
+        "#};
+        let settings = Settings {
+            indent_style: String::from("tab"),
+            indent_tables: true,
+            array_auto_collapse: true,
+            compact_arrays: true,
+            align_entries: true,
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).unwrap();
+        let expected = "[tool.black]\nline-length    = 120\ntarget-version = [\"py39\"]\n\t[tool.black.sub]\n\ta = [\"b\", \"c\"]\n";
+        assert_eq!(got, expected);
+        let second = format_toml(got.as_str(), &settings).unwrap();
         assert_eq!(second, got);
     }
+
+    #[rstest]
+    fn test_migrate_poetry() {
+        let start = indoc! {r#"
+        [tool.poetry]
+        name = "Demo"
+        version = "1.0.0"
+        description = "a demo"
+
+        [tool.poetry.dependencies]
+        python = "^3.10"
+        Requests = "^2.31"
+        b = { git = "https://example.com/b.git" }
+
+        [build-system]
+        requires = ["poetry-core>=2"]
+        build-backend = "poetry.core.masonry.api"
+        "#};
+        let settings = Settings {
+            migrate_poetry: true,
+            ..Settings::default()
+        };
+        let got = format_toml(start, &settings).unwrap();
+        let expected = indoc! {r#"
+        [build-system]
+        build-backend = "poetry.core.masonry.api"
+        requires = [ "poetry-core>=2" ]
+
+        [project]
+        name = "demo"
+        version = "1.0.0"
+        description = "a demo"
+        requires-python = ">=3.10,<4.0"
+        classifiers = [
+          "Programming Language :: Python :: 3 :: Only",
+          "Programming Language :: Python :: 3.10",
+          "Programming Language :: Python :: 3.11",
+          "Programming Language :: Python :: 3.12",
+          "Programming Language :: Python :: 3.13",
+        ]
+        dependencies = [ "requests>=2.31,<3" ]
+
+        [tool.poetry.dependencies]
+        b = { git = "https://example.com/b.git" }
+        "#};
+        assert_eq!(got, expected);
+        assert_eq!(format_toml(&got, &settings).unwrap(), got);
+        let problems = diagnose(start, (3, 9), (3, 13), true)
+            .into_iter()
+            .map(|d| (d.code, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [(
+                "PF106",
+                String::from(
+                    "tool.poetry.dependencies.b cannot be migrated to the project table: the \"git\" key has no PEP 621 \
+                     equivalent"
+                )
+            )]
+        );
+    }
+
+    #[rstest]
+    #[case::valid(Settings::default(), Ok(()))]
+    #[case::bad_choice(
+        Settings { indent_style: String::from("both"), ..Settings::default() },
+        Err(String::from("indent_style must be one of space, tab, got \"both\""))
+    )]
+    #[case::zero_width(
+        Settings { column_width: 0, ..Settings::default() },
+        Err(String::from("column_width must be positive"))
+    )]
+    #[case::zero_indent(
+        Settings { indent: 0, ..Settings::default() },
+        Err(String::from("indent must be positive"))
+    )]
+    #[case::zero_indent_tab(Settings { indent: 0, indent_style: String::from("tab"), ..Settings::default() }, Ok(()))]
+    #[case::blank_lines_not_allowed(
+        Settings { blank_lines_between_groups: 2, ..Settings::default() },
+        Err(String::from("allowed_blank_lines must be at least the blank lines between tables (2), got 1"))
+    )]
+    #[case::blank_lines_allowed(
+        Settings { allowed_blank_lines: 2, blank_lines_between_sub_tables: Some(2), ..Settings::default() },
+        Ok(())
+    )]
+    fn test_validate_settings(#[case] settings: Settings, #[case] expected: Result<(), String>) {
+        assert_eq!(settings.validate(), expected);
+    }
 }
@@ -0,0 +1,421 @@
+use std::iter::zip;
+
+use lexical_sort::natural_lexical_cmp;
+use regex::Regex;
+use taplo::dom::Node;
+use taplo::syntax::SyntaxElement;
+use taplo::syntax::SyntaxKind::{NEWLINE, TABLE_ARRAY_HEADER};
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, string_values, transform};
+use crate::helpers::create::make_newline;
+use crate::helpers::dom::{lookup, strings};
+use crate::helpers::string::update_content;
+use crate::helpers::table::{for_entries, header_of, reorder_table_keys, Tables};
+
+pub fn fix(tables: &Tables) {
+    if let Some(table_element) = tables.get("tool.mypy") {
+        let table = &mut table_element.first().unwrap().borrow_mut();
+        fix_options(table);
+        reorder_table_keys(table, &[&[""], ORDER, &["overrides"]].concat());
+    }
+    let Some(positions) = tables.header_to_pos.get("tool.mypy.overrides") else {
+        return;
+    };
+    // checked upfront, as the overrides get taken apart one by one below
+    if positions
+        .iter()
+        .any(|p| header_of(&tables.table_set[*p].borrow()).map(SyntaxElement::kind) != Some(TABLE_ARRAY_HEADER))
+    {
+        return;
+    }
+    let (mut overrides, mut separators) = (Vec::<(String, Vec<SyntaxElement>)>::new(), Vec::new());
+    let mut unstructured = false;
+    for position in positions {
+        let mut element = tables.table_set[*position].borrow_mut();
+        fix_options(&element);
+        reorder_table_keys(&mut element, &[&["", "module"], ORDER].concat());
+        let mut pattern = String::new();
+        for_entries(&element, &mut |key, entry| {
+            if key == "module" {
+                let patterns = string_values(entry);
+                unstructured |= patterns.iter().any(|p| is_unstructured(p));
+                pattern = patterns.join(" ");
+            }
+        });
+        // the blank lines after a table separate it from the next one, so they stay in place
+        let separator = match element.last() {
+            Some(last) if last.kind() == NEWLINE => element.pop().unwrap(),
+            _ => make_newline(),
+        };
+        separators.push(separator);
+        overrides.push((pattern, element.clone()));
+    }
+    // mypy picks the most specific pattern matching a module, so the order of the overrides carries no meaning, unless
+    // a pattern has a wildcard in the middle: those apply in the order given, the later ones winning
+    if !unstructured {
+        overrides.sort_by(|(a, _), (b, _)| natural_lexical_cmp(a, b));
+    }
+    for ((position, (_, mut element)), separator) in zip(zip(positions, overrides), separators) {
+        element.push(separator);
+        tables.table_set[*position].replace(element);
+    }
+}
+
+/// True for a module pattern with a wildcard other than the trailing `.*`, e.g. `a.*.c`.
+fn is_unstructured(pattern: &str) -> bool {
+    pattern.strip_suffix(".*").unwrap_or(pattern).contains('*')
+}
+
+fn fix_options(table: &[SyntaxElement]) {
+    for_entries(table, &mut |key, entry| match key.as_str() {
+        "python_version" => {
+            update_content(entry, normalize_python_version);
+        }
+        name if SORTED.contains(&name) => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        _ => {}
+    });
+}
+
+/// The version as `MAJOR.MINOR`, the only form mypy accepts; values not looking like a version are left as is.
+pub fn normalize_python_version(value: &str) -> String {
+    let re = Regex::new(r"^(?:py(?:thon)?)?(\d+)\.(\d+)(?:\.\d+)*$").unwrap();
+    let value = value.trim();
+    re.captures(value).map_or_else(
+        || String::from(value),
+        |caps| {
+            let number = |at: usize| {
+                caps[at]
+                    .parse::<u64>()
+                    .map_or_else(|_| String::from(&caps[at]), |n| n.to_string())
+            };
+            format!("{}.{}", number(1), number(2))
+        },
+    )
+}
+
+/// The options in the order of the mypy configuration documentation.
+const ORDER: &[&str] = &[
+    // import discovery
+    "mypy_path",
+    "files",
+    "modules",
+    "packages",
+    "exclude",
+    "namespace_packages",
+    "explicit_package_bases",
+    "ignore_missing_imports",
+    "follow_untyped_imports",
+    "follow_imports",
+    "follow_imports_for_stubs",
+    "python_executable",
+    "no_site_packages",
+    "no_silence_site_packages",
+    // platform configuration
+    "python_version",
+    "platform",
+    "always_true",
+    "always_false",
+    // disallow dynamic typing
+    "disallow_any_unimported",
+    "disallow_any_expr",
+    "disallow_any_decorated",
+    "disallow_any_explicit",
+    "disallow_any_generics",
+    "disallow_subclassing_any",
+    // untyped definitions and calls
+    "disallow_untyped_calls",
+    "untyped_calls_exclude",
+    "disallow_untyped_defs",
+    "disallow_incomplete_defs",
+    "check_untyped_defs",
+    "disallow_untyped_decorators",
+    // none and optional handling
+    "implicit_optional",
+    "strict_optional",
+    // configuring warnings
+    "warn_redundant_casts",
+    "warn_unused_ignores",
+    "warn_no_return",
+    "warn_return_any",
+    "warn_unreachable",
+    // suppressing errors
+    "ignore_errors",
+    // miscellaneous strictness flags
+    "allow_untyped_globals",
+    "allow_redefinition",
+    "local_partial_types",
+    "disable_error_code",
+    "enable_error_code",
+    "extra_checks",
+    "implicit_reexport",
+    "strict_concatenate",
+    "strict_equality",
+    "strict",
+    // configuring error messages
+    "show_error_context",
+    "show_column_numbers",
+    "show_error_end",
+    "hide_error_codes",
+    "show_error_code_links",
+    "pretty",
+    "color_output",
+    "error_summary",
+    "show_absolute_path",
+    "force_uppercase_builtins",
+    "force_union_syntax",
+    // incremental mode
+    "incremental",
+    "cache_dir",
+    "sqlite_cache",
+    "cache_fine_grained",
+    "skip_version_check",
+    "skip_cache_mtime_checks",
+    // advanced options
+    "plugins",
+    "pdb",
+    "show_traceback",
+    "raise_exceptions",
+    "custom_typing_module",
+    "custom_typeshed_dir",
+    "warn_incomplete_stub",
+    "enable_incomplete_feature",
+    // report generation
+    "any_exprs_report",
+    "cobertura_xml_report",
+    "html_report",
+    "xslt_html_report",
+    "linecount_report",
+    "linecoverage_report",
+    "lineprecision_report",
+    "txt_report",
+    "xslt_txt_report",
+    "xml_report",
+    // miscellaneous
+    "junit_xml",
+    "scripts_are_modules",
+    "warn_unused_configs",
+    "verbosity",
+];
+
+/// The options holding lists of patterns, modules or codes, whose order carries no meaning.
+const SORTED: &[&str] = &[
+    "module",
+    "plugins",
+    "exclude",
+    "files",
+    "modules",
+    "packages",
+    "always_true",
+    "always_false",
+    "disable_error_code",
+    "enable_error_code",
+    "enable_incomplete_feature",
+    "untyped_calls_exclude",
+];
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| path == "tool.mypy.python_version" || path == "tool.mypy.overrides.python_version",
+        string: |value, _| normalize_python_version(value),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "tool.mypy.overrides",
+        order: Order::AnyIf(|overrides| {
+            !overrides.iter().any(|item| {
+                let module = lookup(item, &["module"]);
+                let patterns = module.as_ref().and_then(Node::as_str).map_or_else(
+                    || strings(module.as_ref()).into_iter().map(|(p, _)| p).collect(),
+                    |p| vec![String::from(p.value())],
+                );
+                patterns.iter().any(|p| is_unstructured(p))
+            })
+        }),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| {
+            path.strip_prefix("tool.mypy.overrides.")
+                .or_else(|| path.strip_prefix("tool.mypy."))
+                .is_some_and(|key| SORTED.contains(&key))
+        },
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::mypy::{fix, normalize_python_version};
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let tables = Tables::from_ast(&root_ast, false);
+        fix(&tables);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_mypy(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::options(
+        indoc ! {r#"
+    [tool.mypy]
+    strict = true
+    plugins = ["pydantic.mypy", "Django_stubs"]
+    python_version = " 3.08 "
+    exclude = ["b/", "a/"]
+    mypy_path = "src:stubs"
+    unknown_option = 1
+    "#},
+        indoc ! {r#"
+    [tool.mypy]
+    mypy_path = "src:stubs"
+    exclude = [
+      "a/",
+      "b/",
+    ]
+    python_version = "3.8"
+    strict = true
+    plugins = [
+      "Django_stubs",
+      "pydantic.mypy",
+    ]
+    unknown_option = 1
+    "#},
+    )]
+    #[case::exclude_as_regex(
+        indoc ! {r#"
+    [tool.mypy]
+    exclude = "^build/"
+    "#},
+        indoc ! {r#"
+    [tool.mypy]
+    exclude = "^build/"
+    "#},
+    )]
+    #[case::overrides(
+        indoc ! {r#"
+    [tool.mypy]
+    strict = true
+
+    [[tool.mypy.overrides]]
+    ignore_missing_imports = true
+    module = ["yaml.*", "requests"]
+    disallow_untyped_defs = false
+
+    [[tool.mypy.overrides]]
+    # tests are less strict
+    module = "tests.*"
+    disallow_untyped_defs = false
+
+    [[tool.mypy.overrides]]
+    module = "a.b"
+    ignore_errors = true
+    "#},
+        indoc ! {r#"
+    [tool.mypy]
+    strict = true
+
+    [[tool.mypy.overrides]]
+    module = "a.b"
+    ignore_errors = true
+
+    [[tool.mypy.overrides]]
+    module = [
+      "requests",
+      "yaml.*",
+    ]
+    ignore_missing_imports = true
+    disallow_untyped_defs = false
+
+    [[tool.mypy.overrides]]
+    # tests are less strict
+    module = "tests.*"
+    disallow_untyped_defs = false
+    "#},
+    )]
+    #[case::overrides_unstructured(
+        indoc ! {r#"
+    [[tool.mypy.overrides]]
+    module = "b.*"
+    ignore_errors = true
+
+    [[tool.mypy.overrides]]
+    module = "a.*.tests"
+    ignore_errors = false
+    "#},
+        indoc ! {r#"
+    [[tool.mypy.overrides]]
+    module = "b.*"
+    ignore_errors = true
+
+    [[tool.mypy.overrides]]
+    module = "a.*.tests"
+    ignore_errors = false
+    "#},
+    )]
+    #[case::overrides_not_an_array(
+        indoc ! {r#"
+    [tool.mypy.overrides]
+    module = "c"
+
+    [[tool.mypy.overrides]]
+    module = "b"
+    ignore_errors = true
+
+    [[tool.mypy.overrides]]
+    module = "a"
+    "#},
+        indoc ! {r#"
+    [tool.mypy.overrides]
+    module = "c"
+
+    [[tool.mypy.overrides]]
+    module = "b"
+    ignore_errors = true
+
+    [[tool.mypy.overrides]]
+    module = "a"
+    "#},
+    )]
+    fn test_format_mypy(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+
+    #[rstest]
+    #[case::already_normal("3.12", "3.12")]
+    #[case::whitespace(" 3.12 ", "3.12")]
+    #[case::leading_zero("3.08", "3.8")]
+    #[case::patch("3.11.4", "3.11")]
+    #[case::prefix("py310", "py310")]
+    #[case::prefix_with_dot("py3.10", "3.10")]
+    #[case::not_a_version("latest", "latest")]
+    fn test_normalize_python_version(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(normalize_python_version(start), expected);
+    }
+}
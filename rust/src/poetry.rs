@@ -0,0 +1,1216 @@
+use std::cell::RefMut;
+use std::cmp::Ordering;
+use std::iter::once;
+use std::mem::take;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use lexical_sort::natural_lexical_cmp;
+use pep440_rs::VersionSpecifiers;
+use pep508_rs::Requirement;
+use regex::Regex;
+use taplo::dom::node::TableKind;
+use taplo::dom::Node;
+use taplo::parser::parse;
+use taplo::syntax::SyntaxElement;
+use taplo::syntax::SyntaxKind::{ARRAY, COMMENT, ENTRY, NEWLINE, VALUE, WHITESPACE};
+use taplo::HashSet;
+
+use crate::check::{Moved, Normalization};
+use crate::helpers::create::{basic_string, make_comment, make_entry, make_newline};
+use crate::helpers::dom::{lookup, strings};
+use crate::helpers::pep508::get_canonic_package_name;
+use crate::helpers::table::{
+    for_entries, key_parts, reorder_inline_table_keys, reorder_table_keys, reorder_tables, set_key, split_name, Tables,
+};
+
+pub fn fix(tables: &mut Tables) {
+    let mut names = tables
+        .header_to_pos
+        .keys()
+        .filter(|name| is_dependency_table(name))
+        .cloned()
+        .collect::<Vec<String>>();
+    names.sort();
+    for name in names {
+        for table in tables.get(&name).unwrap() {
+            fix_dependencies(&mut table.borrow_mut());
+        }
+    }
+    order_groups(tables);
+}
+
+/// True for the tables listing dependencies: the main and legacy dev ones, and those of the dependency groups.
+fn is_dependency_table(name: &str) -> bool {
+    match split_name(name).as_slice() {
+        [tool, poetry, dependencies] => {
+            tool == "tool"
+                && poetry == "poetry"
+                && (dependencies == "dependencies" || dependencies == "dev-dependencies")
+        }
+        [tool, poetry, group, _, dependencies] => {
+            tool == "tool" && poetry == "poetry" && group == "group" && dependencies == "dependencies"
+        }
+        _ => false,
+    }
+}
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        // the package names are case-insensitive
+        applies: |path, _| is_dependency_table(path),
+        key: str::to_lowercase,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, opt| opt.migrate_poetry && is_movable(path),
+        moved: Some(moved),
+        ..Normalization::NONE
+    },
+];
+
+/// True for the poetry settings with a PEP 621 equivalent, which the migration may move into the project table.
+fn is_movable(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix("tool.poetry.") else {
+        return false;
+    };
+    match rest.split_once('.') {
+        None => METADATA.contains(&rest) || URLS.iter().any(|(key, _)| *key == rest),
+        Some((table, _)) => ["urls", "scripts", "extras", "dependencies"].contains(&table),
+    }
+}
+
+/// Where the migration puts the poetry setting at the dotted path, see [`migrate`].
+fn moved(path: &str, key: &str, node: &Node, root: &Node) -> Option<Vec<Moved>> {
+    let whole = |path: String, value: String| {
+        Some(vec![Moved {
+            path,
+            value,
+            element: false,
+        }])
+    };
+    let rest = path.strip_prefix("tool.poetry.")?;
+    match rest.split_once('.').map_or(rest, |(table, _)| table) {
+        "name" | "version" | "description" => whole(format!("project.{key}"), basic_string(node.as_str()?.value())),
+        "readme" => {
+            let file = match node {
+                Node::Str(value) => String::from(value.value()),
+                _ => match all_strings(node)?.as_slice() {
+                    [file] => file.clone(),
+                    _ => return None,
+                },
+            };
+            whole(String::from("project.readme"), basic_string(&file))
+        }
+        "license" => whole(
+            String::from("project.license.text"),
+            basic_string(node.as_str()?.value()),
+        ),
+        "keywords" | "classifiers" => {
+            let values = all_strings(node)?
+                .iter()
+                .map(|v| basic_string(v))
+                .collect::<Vec<String>>();
+            whole(format!("project.{key}"), format!("[{}]", values.join(", ")))
+        }
+        "authors" | "maintainers" => {
+            let people = all_strings(node)?.iter().map(|v| person(v)).collect::<Vec<String>>();
+            whole(format!("project.{key}"), format!("[{}]", people.join(", ")))
+        }
+        "homepage" | "repository" | "documentation" => {
+            let label = format!("{}{}", key[..1].to_uppercase(), &key[1..]);
+            whole(format!("project.urls.{label}"), basic_string(node.as_str()?.value()))
+        }
+        "urls" => whole(format!("project.urls.{key}"), basic_string(node.as_str()?.value())),
+        "scripts" => whole(format!("project.scripts.{key}"), basic_string(node.as_str()?.value())),
+        "extras" => whole(format!("project.optional-dependencies.{key}"), extra(node, root)?),
+        "dependencies" if key == "python" => {
+            let specifiers = to_pep440(node.as_str()?.value())?;
+            if specifiers.is_empty() {
+                return Some(Vec::new());
+            }
+            whole(String::from("project.requires-python"), basic_string(&specifiers))
+        }
+        "dependencies" => match requirement(key, node)? {
+            (requirement, false) => Some(vec![Moved {
+                path: String::from("project.dependencies"),
+                value: basic_string(&requirement),
+                element: true,
+            }]),
+            // an optional dependency goes into the extras listing it
+            (_, true) => {
+                let Some(Node::Table(extras)) = lookup(root, &["tool", "poetry", "extras"]) else {
+                    return None;
+                };
+                let mut places = Vec::<Moved>::new();
+                for (name, listed) in extras.entries().read().iter() {
+                    let names = all_strings(listed)?;
+                    if names.iter().any(|n| canonical_name(n) == canonical_name(key)) {
+                        let path = format!("project.optional-dependencies.{}", name.value());
+                        places.push(Moved {
+                            path,
+                            value: extra(listed, root)?,
+                            element: false,
+                        });
+                    }
+                }
+                (!places.is_empty()).then_some(places)
+            }
+        },
+        _ => None,
+    }
+}
+
+/// The string values of the array, `None` unless all of its values are strings.
+fn all_strings(node: &Node) -> Option<Vec<String>> {
+    let values = strings(Some(node));
+    (node.as_array()?.items().read().len() == values.len()).then(|| values.into_iter().map(|(v, _)| v).collect())
+}
+
+/// The PEP 621 author or maintainer for a poetry one of the form `name <email>`, written as TOML.
+fn person(value: &str) -> String {
+    match value.trim().strip_suffix('>').and_then(|v| v.rsplit_once('<')) {
+        Some((name, email)) if name.trim().is_empty() => format!("{{ email = {} }}", basic_string(email.trim())),
+        Some((name, email)) => format!(
+            "{{ name = {}, email = {} }}",
+            basic_string(name.trim()),
+            basic_string(email.trim())
+        ),
+        None => format!("{{ name = {} }}", basic_string(value.trim())),
+    }
+}
+
+/// The requirements of the extra listing the dependencies of the document at the root, written as TOML.
+fn extra(listed: &Node, root: &Node) -> Option<String> {
+    let Some(Node::Table(dependencies)) = lookup(root, &["tool", "poetry", "dependencies"]) else {
+        return None;
+    };
+    let entries = dependencies.entries().read();
+    let mut requirements = Vec::<String>::new();
+    for name in all_strings(listed)? {
+        let (key, node) = entries
+            .iter()
+            .find(|(key, _)| canonical_name(key.value()) == canonical_name(&name))?;
+        requirements.push(basic_string(&requirement(key.value(), node)?.0));
+    }
+    Some(format!("[{}]", requirements.join(", ")))
+}
+
+/// The poetry dependency as a PEP 508 requirement and whether it is optional, `None` without an equivalent.
+fn requirement(name: &str, node: &Node) -> Option<(String, bool)> {
+    let (mut version, mut extras, mut markers, mut optional) = (String::from("*"), Vec::new(), None, false);
+    match node {
+        Node::Str(value) => version = String::from(value.value()),
+        Node::Table(table) => {
+            for (key, value) in table.entries().read().iter() {
+                match key.value() {
+                    "version" => version = String::from(value.as_str()?.value()),
+                    "extras" => extras = all_strings(value)?,
+                    "markers" => markers = Some(String::from(value.as_str()?.value())),
+                    "optional" => optional = value.as_bool()?.value(),
+                    _ => return None,
+                }
+            }
+        }
+        _ => return None,
+    }
+    let mut requirement = String::from(name);
+    if !extras.is_empty() {
+        requirement.push_str(&format!("[{}]", extras.join(",")));
+    }
+    requirement.push_str(&to_pep440(&version)?);
+    if let Some(markers) = markers {
+        requirement.push_str(&format!("; {markers}"));
+    }
+    Some((requirement, optional))
+}
+
+fn fix_dependencies(table: &mut RefMut<Vec<SyntaxElement>>) {
+    let mut packages = Vec::<String>::new();
+    for element in table.iter().filter(|e| e.kind() == ENTRY) {
+        let mut parts = key_parts(element);
+        if parts.is_empty() {
+            continue;
+        }
+        // poetry compares package names case-insensitively
+        let package = parts[0].to_lowercase();
+        if parts[0] != package {
+            parts[0].clone_from(&package);
+            set_key(element, &parts.join("."));
+        }
+        if !packages.contains(&package) {
+            packages.push(package);
+        }
+    }
+    for_entries(table, &mut |_, entry| {
+        reorder_inline_table_keys(entry, INLINE_ORDER);
+        // multiple constraints are an array of inline tables
+        for array in entry.children().filter(|n| n.kind() == ARRAY) {
+            for value in array.children().filter(|n| n.kind() == VALUE) {
+                reorder_inline_table_keys(&value, INLINE_ORDER);
+            }
+        }
+    });
+    packages.sort_by_cached_key(|package| (package != "python", canonical_name(package)));
+    let order = once("")
+        .chain(packages.iter().map(String::as_str))
+        .collect::<Vec<&str>>();
+    reorder_table_keys(table, &order);
+}
+
+fn canonical_name(package: &str) -> String {
+    get_canonic_package_name(package.trim_matches(['"', '\'']))
+}
+
+/// Order the `tool.poetry.group.<name>` tables by the group name, the tables of a group keep their relative order.
+fn order_groups(tables: &mut Tables) {
+    let mut groups = tables
+        .header_to_pos
+        .iter()
+        .filter(|(name, _)| {
+            let parts = split_name(name);
+            parts.len() > 3 && parts[..3] == ["tool", "poetry", "group"]
+        })
+        .map(|(name, positions)| (positions.clone(), name.clone()))
+        .collect::<Vec<(Vec<usize>, String)>>();
+    if groups.iter().any(|(positions, _)| positions.len() != 1) {
+        return;
+    }
+    groups.sort();
+    groups.sort_by(|(_, a), (_, b)| {
+        let (a, b) = (split_name(a), split_name(b));
+        natural_lexical_cmp(a[3].trim_matches(['"', '\'']), b[3].trim_matches(['"', '\'']))
+    });
+    let names = groups.into_iter().map(|(_, name)| name).collect::<Vec<String>>();
+    reorder_tables(tables, &names);
+}
+
+/// Move the poetry metadata (name, version, description, readme, license, keywords, classifiers, authors,
+/// maintainers, urls, dependencies, extras and scripts) into the `project` table. The settings without a PEP 621
+/// equivalent stay in place, see [`migration_problems`].
+pub fn migrate(content: &str, tables: &mut Tables) {
+    let migration = plan(&parse(content).into_dom());
+    if migration.moved.is_empty() {
+        return;
+    }
+    let project = if migration.entries.is_empty() {
+        None
+    } else {
+        if !tables.header_to_pos.contains_key("project") {
+            end_with_newline(tables);
+        }
+        let Some(position) = tables.ensure("project") else {
+            return;
+        };
+        Some(position)
+    };
+    let comments = remove_moved(tables, &migration);
+    if let Some(position) = project {
+        let mut table = tables.table_set[position].borrow_mut();
+        let trailing = match table.last() {
+            Some(last) if last.kind() == NEWLINE => table.pop(),
+            _ => None,
+        };
+        for (at, entry) in migration.entries.iter().enumerate() {
+            // the comments about the moved settings go above the entry they became (part of)
+            for (_, comment) in comments.iter().filter(|(into, _)| *into == at) {
+                table.push(make_newline());
+                table.push(make_comment(comment));
+            }
+            table.push(make_newline());
+            table.push(make_entry(entry));
+        }
+        table.push(trailing.unwrap_or_else(make_newline));
+    }
+}
+
+/// The poetry settings the migration leaves in place, with the reason and the value they are about.
+pub fn migration_problems(root: &Node) -> Vec<(String, Node)> {
+    plan(root).problems
+}
+
+/// The outcome of the migration: the entries added to the `project` table, the paths (within `tool.poetry`) of the
+/// settings moved there with the entry each became (part of), and the settings left in place.
+#[derive(Default)]
+struct Migration {
+    entries: Vec<String>,
+    moved: Vec<Vec<String>>,
+    into: Vec<Option<usize>>,
+    problems: Vec<(String, Node)>,
+}
+
+impl Migration {
+    fn add(&mut self, path: &[&str], entry: Option<String>) {
+        self.into.push(entry.is_some().then_some(self.entries.len()));
+        self.entries.extend(entry);
+        self.moved.push(path.iter().map(|p| String::from(*p)).collect());
+    }
+
+    /// Add the `entry` the settings moved at the indexes `parts` are joined into.
+    fn join(&mut self, parts: &[usize], entry: String) {
+        for part in parts {
+            self.into[*part] = Some(self.entries.len());
+        }
+        self.entries.push(entry);
+    }
+
+    fn problem(&mut self, path: &[&str], reason: &str, node: &Node) {
+        self.problems.push((
+            format!(
+                "tool.poetry.{} cannot be migrated to the project table: {reason}",
+                path.join(".")
+            ),
+            node.clone(),
+        ));
+    }
+
+    /// The outcome for a table holding `count` settings, one written inline can only be moved as a whole.
+    fn settle(mut self, inline: bool, count: usize) -> Self {
+        if inline && self.moved.len() != count {
+            self.entries.clear();
+            self.moved.clear();
+            self.into.clear();
+        }
+        self
+    }
+
+    fn merge(&mut self, stage: Self) {
+        let offset = self.entries.len();
+        self.into
+            .extend(stage.into.into_iter().map(|into| into.map(|at| at + offset)));
+        self.entries.extend(stage.entries);
+        self.moved.extend(stage.moved);
+        self.problems.extend(stage.problems);
+    }
+}
+
+fn plan(root: &Node) -> Migration {
+    let mut migration = Migration::default();
+    let Some(poetry) = lookup(root, &["tool", "poetry"]) else {
+        return migration;
+    };
+    let in_project = |path: &[&str]| lookup(root, &[&["project"], path].concat()).is_some();
+    migration.merge(plan_metadata(&poetry, &in_project));
+    let (python, dependencies) = load_dependencies(&poetry);
+    // the optional dependencies are installed through the extras, so these move together
+    let (extras, in_moved_extra, in_kept_extra) = plan_extras(&poetry, &dependencies, &in_project);
+    if let Some(Node::Table(table)) = lookup(&poetry, &["dependencies"]) {
+        let mut stage = Migration::default();
+        if let Some((key, node)) = &python {
+            let path = ["dependencies", key.as_str()];
+            match node.as_str().and_then(|value| to_pep440(value.value())) {
+                None => stage.problem(&path, "the constraint has no PEP 440 equivalent", node),
+                Some(_) if in_project(&["requires-python"]) => {
+                    stage.problem(&path, "project.requires-python is already set", node);
+                }
+                Some(specifiers) => {
+                    let entry =
+                        (!specifiers.is_empty()).then(|| format!("requires-python = {}", basic_string(&specifiers)));
+                    stage.add(&path, entry);
+                }
+            }
+        }
+        let (mut requirements, mut parts) = (Vec::<String>::new(), Vec::<usize>::new());
+        for (key, node, requirement) in &dependencies {
+            let path = ["dependencies", key.as_str()];
+            let name = canonical_name(key);
+            match requirement {
+                Err(reason) => stage.problem(&path, reason, node),
+                // still needed by an extra left in place
+                Ok((_, true)) if in_kept_extra.contains(&name) => {}
+                Ok((_, true)) if in_moved_extra.contains(&name) => stage.add(&path, None),
+                Ok((_, true)) => stage.problem(&path, "the optional dependency is not part of any extra", node),
+                Ok(_) if in_project(&["dependencies"]) => {
+                    stage.problem(&path, "project.dependencies is already set", node);
+                }
+                Ok((requirement, false)) => {
+                    requirements.push(basic_string(requirement));
+                    parts.push(stage.moved.len());
+                    stage.add(&path, None);
+                }
+            }
+        }
+        if !requirements.is_empty() {
+            stage.join(&parts, format!("dependencies = [{}]", requirements.join(", ")));
+        }
+        let count = dependencies.len() + usize::from(python.is_some());
+        migration.merge(stage.settle(table.kind() == TableKind::Inline, count));
+    }
+    migration.merge(extras);
+    migration.merge(plan_scripts(&poetry, &in_project));
+    migration
+}
+
+/// A dependency by its key, with its requirement and whether it is optional.
+type Dependency = (String, Node, Result<(String, bool), String>);
+
+fn plan_metadata(poetry: &Node, in_project: &dyn Fn(&[&str]) -> bool) -> Migration {
+    let mut stage = Migration::default();
+    for key in METADATA {
+        let Some(node) = lookup(poetry, &[key]) else {
+            continue;
+        };
+        if in_project(&[key]) {
+            stage.problem(&[key], &format!("project.{key} is already set"), &node);
+            continue;
+        }
+        match metadata(key, &node) {
+            Ok(value) => stage.add(&[key], Some(format!("{key} = {value}"))),
+            Err(reason) => stage.problem(&[key], reason, &node),
+        }
+    }
+    let mut labels = Vec::<String>::new();
+    let mut plan_url = |stage: &mut Migration, path: &[&str], label: &str, node: &Node| {
+        if in_project(&["urls", label]) {
+            stage.problem(path, &format!("project.urls.{label} is already set"), node);
+        } else if labels.iter().any(|l| l == label) {
+            stage.problem(
+                path,
+                &format!("project.urls.{label} is also set by another setting"),
+                node,
+            );
+        } else if let Some(value) = node.as_str() {
+            let entry = format!("urls.{} = {}", key_of(label), basic_string(value.value()));
+            stage.add(path, Some(entry));
+        } else {
+            stage.problem(path, "the value is not a string", node);
+        }
+        labels.push(String::from(label));
+    };
+    for (key, label) in URLS {
+        if let Some(node) = lookup(poetry, &[key]) {
+            plan_url(&mut stage, &[key], label, &node);
+        }
+    }
+    if let Some(Node::Table(table)) = lookup(poetry, &["urls"]) {
+        let mut listed = Migration::default();
+        let entries = table.entries().read();
+        for (key, node) in entries.iter() {
+            plan_url(&mut listed, &["urls", key.value()], key.value(), node);
+        }
+        stage.merge(listed.settle(table.kind() == TableKind::Inline, entries.len()));
+    }
+    stage
+}
+
+/// The value of the metadata `key` for the project table, or why it has none.
+fn metadata(key: &str, node: &Node) -> Result<String, &'static str> {
+    let items = strings(Some(node));
+    let all_strings = node.as_array().map(|a| a.items().read().len()) == Some(items.len());
+    match key {
+        "readme" => match (node.as_str(), items.as_slice()) {
+            (Some(value), _) => Ok(basic_string(value.value())),
+            (None, [(file, _)]) if all_strings => Ok(basic_string(file)),
+            (None, [_, _, ..]) if all_strings => Err("multiple readme files have no PEP 621 equivalent"),
+            _ => Err("the value is not a string"),
+        },
+        // poetry accepts license names that are not SPDX expressions (e.g. Proprietary), which only the text form holds
+        "license" => node
+            .as_str()
+            .map(|value| format!("{{ text = {} }}", basic_string(value.value())))
+            .ok_or("the value is not a string"),
+        "keywords" | "classifiers" | "authors" | "maintainers" if all_strings => {
+            let people = key == "authors" || key == "maintainers";
+            let values = items
+                .iter()
+                .map(|(value, _)| if people { author(value) } else { basic_string(value) })
+                .collect::<Vec<String>>();
+            Ok(format!("[{}]", values.join(", ")))
+        }
+        "keywords" | "classifiers" | "authors" | "maintainers" => Err("the value is not an array of strings"),
+        _ => node
+            .as_str()
+            .map(|value| basic_string(value.value()))
+            .ok_or("the value is not a string"),
+    }
+}
+
+/// The `python` constraint and the other dependencies.
+fn load_dependencies(poetry: &Node) -> (Option<(String, Node)>, Vec<Dependency>) {
+    let (mut python, mut dependencies) = (None, Vec::<Dependency>::new());
+    if let Some(Node::Table(table)) = lookup(poetry, &["dependencies"]) {
+        for (key, node) in table.entries().read().iter() {
+            if key.value().eq_ignore_ascii_case("python") {
+                python = Some((String::from(key.value()), node.clone()));
+            } else {
+                let requirement = to_requirement(key.value(), node);
+                dependencies.push((String::from(key.value()), node.clone(), requirement));
+            }
+        }
+    }
+    (python, dependencies)
+}
+
+/// The extras moved, with the canonical names of the dependencies used by the extras moved and by those left in place.
+fn plan_extras(
+    poetry: &Node,
+    dependencies: &[Dependency],
+    in_project: &dyn Fn(&[&str]) -> bool,
+) -> (Migration, HashSet<String>, HashSet<String>) {
+    let (mut in_moved, mut in_kept) = (HashSet::<String>::new(), HashSet::<String>::new());
+    let Some(Node::Table(table)) = lookup(poetry, &["extras"]) else {
+        return (Migration::default(), in_moved, in_kept);
+    };
+    let (mut stage, mut needs) = (Migration::default(), Vec::<(Vec<String>, bool)>::new());
+    let entries = table.entries().read();
+    for (key, node) in entries.iter() {
+        let extra = key.value();
+        let packages = strings(Some(node));
+        let found = packages
+            .iter()
+            .map(|(package, _)| {
+                let name = canonical_name(package);
+                (
+                    package,
+                    dependencies.iter().find(|(key, ..)| canonical_name(key) == name),
+                )
+            })
+            .collect::<Vec<_>>();
+        let used = found
+            .iter()
+            .filter_map(|(_, dependency)| dependency.map(|(key, ..)| canonical_name(key)))
+            .collect::<Vec<String>>();
+        let reason = if in_project(&["optional-dependencies", extra]) {
+            Some(format!("project.optional-dependencies.{extra} is already set"))
+        } else if node.as_array().map(|a| a.items().read().len()) == Some(packages.len()) {
+            found.iter().find_map(|(package, dependency)| match dependency {
+                None => Some(format!("the dependency {package:?} is not declared")),
+                Some((_, _, Err(_))) => Some(format!("the dependency {package:?} cannot be migrated")),
+                Some(_) => None,
+            })
+        } else {
+            Some(String::from("the value is not an array of strings"))
+        };
+        if let Some(reason) = reason {
+            stage.problem(&["extras", extra], &reason, node);
+            needs.push((used, false));
+        } else {
+            let requirements = found
+                .iter()
+                .filter_map(|(_, dependency)| match dependency {
+                    Some((_, _, Ok((requirement, _)))) => Some(basic_string(requirement)),
+                    _ => None,
+                })
+                .collect::<Vec<String>>();
+            let entry = format!(
+                "optional-dependencies.{} = [{}]",
+                key_of(extra),
+                requirements.join(", ")
+            );
+            stage.add(&["extras", extra], Some(entry));
+            needs.push((used, true));
+        }
+    }
+    let stage = stage.settle(table.kind() == TableKind::Inline, entries.len());
+    for (used, moves) in needs {
+        if moves && !stage.moved.is_empty() {
+            in_moved.extend(used);
+        } else {
+            in_kept.extend(used);
+        }
+    }
+    (stage, in_moved, in_kept)
+}
+
+fn plan_scripts(poetry: &Node, in_project: &dyn Fn(&[&str]) -> bool) -> Migration {
+    let mut stage = Migration::default();
+    let Some(Node::Table(table)) = lookup(poetry, &["scripts"]) else {
+        return stage;
+    };
+    let entries = table.entries().read();
+    for (key, node) in entries.iter() {
+        let script = key.value();
+        if in_project(&["scripts", script]) {
+            let reason = format!("project.scripts.{script} is already set");
+            stage.problem(&["scripts", script], &reason, node);
+        } else if let Some(value) = node.as_str() {
+            let entry = format!("scripts.{} = {}", key_of(script), basic_string(value.value()));
+            stage.add(&["scripts", script], Some(entry));
+        } else {
+            let reason = "only scripts given as a reference to a function have a PEP 621 equivalent";
+            stage.problem(&["scripts", script], reason, node);
+        }
+    }
+    stage.settle(table.kind() == TableKind::Inline, entries.len())
+}
+
+/// The PEP 621 author for a poetry one of the form `name <email>`.
+fn author(value: &str) -> String {
+    AUTHOR.captures(value).map_or_else(
+        || format!("{{ name = {} }}", basic_string(value.trim())),
+        |caps| {
+            if caps[1].is_empty() {
+                format!("{{ email = {} }}", basic_string(&caps[2]))
+            } else {
+                format!(
+                    "{{ name = {}, email = {} }}",
+                    basic_string(&caps[1]),
+                    basic_string(&caps[2])
+                )
+            }
+        },
+    )
+}
+
+/// The dependency as a PEP 508 requirement and whether it is optional.
+fn to_requirement(name: &str, node: &Node) -> Result<(String, bool), String> {
+    let (mut constraint, mut extras, mut markers, mut optional) = (String::from("*"), Vec::new(), None, false);
+    match node {
+        Node::Str(value) => constraint = String::from(value.value()),
+        Node::Table(table) => {
+            for (key, value) in table.entries().read().iter() {
+                match (key.value(), value) {
+                    ("version", Node::Str(version)) => constraint = String::from(version.value()),
+                    ("extras", Node::Array(_)) => extras = strings(Some(value)).into_iter().map(|(e, _)| e).collect(),
+                    ("markers", Node::Str(value)) => markers = Some(String::from(value.value())),
+                    ("optional", Node::Bool(value)) => optional = value.value(),
+                    (key, _) => return Err(format!("the {key:?} key has no PEP 621 equivalent")),
+                }
+            }
+        }
+        Node::Array(_) => return Err(String::from("multiple constraints have no PEP 621 equivalent")),
+        _ => return Err(String::from("the value is not a string or a table")),
+    }
+    let specifiers =
+        to_pep440(&constraint).ok_or_else(|| format!("the constraint {constraint:?} has no PEP 440 equivalent"))?;
+    let mut requirement = String::from(name);
+    if !extras.is_empty() {
+        requirement.push_str(&format!("[{}]", extras.join(",")));
+    }
+    requirement.push_str(&specifiers);
+    if let Some(markers) = markers {
+        requirement.push_str(&format!("; {markers}"));
+    }
+    Requirement::from_str(&requirement).map_err(|_| format!("{requirement:?} is not a valid requirement"))?;
+    Ok((requirement, optional))
+}
+
+/// The poetry version constraint as PEP 440 specifiers (empty for any version), `None` if there is no equivalent
+/// (e.g. for alternatives joined by `||`).
+pub fn to_pep440(constraint: &str) -> Option<String> {
+    if constraint.contains('|') {
+        return None;
+    }
+    // the parts are joined by commas or whitespace, an operator may be apart from its version
+    let (mut tokens, mut pending) = (Vec::<String>::new(), String::new());
+    for token in constraint.replace(',', " ").split_whitespace() {
+        pending.push_str(token);
+        if !token.chars().all(|c| "^~=!<>".contains(c)) {
+            tokens.push(take(&mut pending));
+        }
+    }
+    if !pending.is_empty() {
+        return None;
+    }
+    let mut specifiers = Vec::<String>::new();
+    for token in tokens {
+        let caps = CONSTRAINT.captures(&token)?;
+        let (operator, version) = (caps.get(1).map_or("", |m| m.as_str()), &caps[2]);
+        specifiers.push(match operator {
+            "" | "=" if version == "*" => continue,
+            // the first non-zero part of the release may not change
+            "^" => bounded(version, |release| {
+                release.iter().position(|p| *p != 0).unwrap_or(release.len() - 1)
+            })?,
+            // the minor version may not change, the major one if only that is given
+            "~" => bounded(version, |release| usize::from(release.len() > 1))?,
+            "" | "=" => format!("=={version}"),
+            _ => format!("{operator}{version}"),
+        });
+    }
+    let specifiers = specifiers.join(",");
+    VersionSpecifiers::from_str(&specifiers).ok()?;
+    Some(specifiers)
+}
+
+/// At least `version`, below the version with the part of the release selected by `at` bumped.
+fn bounded(version: &str, at: fn(&[u64]) -> usize) -> Option<String> {
+    let release = version
+        .split('.')
+        .map_while(|part| part.parse::<u64>().ok())
+        .collect::<Vec<u64>>();
+    if release.is_empty() {
+        return None;
+    }
+    let at = at(&release);
+    let upper = release
+        .iter()
+        .enumerate()
+        .map(|(index, part)| match index.cmp(&at) {
+            Ordering::Less => part.to_string(),
+            Ordering::Equal => (part + 1).to_string(),
+            Ordering::Greater => String::from("0"),
+        })
+        .collect::<Vec<String>>()
+        .join(".");
+    Some(format!(">={version},<{upper}"))
+}
+
+/// The key as written in a dotted key, quoted unless bare.
+fn key_of(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        String::from(name)
+    } else {
+        basic_string(name)
+    }
+}
+
+/// Remove the entries of the moved settings from the poetry tables, the tables left without entries or comments go
+/// too. Returns the comments about the settings that became (part of) an entry, on the lines right above them or at
+/// the end of their line, with the index of that entry.
+fn remove_moved(tables: &Tables, migration: &Migration) -> Vec<(usize, String)> {
+    let mut comments = Vec::<(usize, String)>::new();
+    for (name, positions) in &tables.header_to_pos {
+        let parts = unquoted(split_name(name));
+        if parts.len() < 2 || parts[..2] != ["tool", "poetry"] {
+            continue;
+        }
+        for position in positions {
+            let mut table = tables.table_set[*position].borrow_mut();
+            let (mut kept, mut removed) = (Vec::<SyntaxElement>::new(), false);
+            let mut elements = table.iter().cloned().peekable();
+            while let Some(element) = elements.next() {
+                if element.kind() == ENTRY {
+                    let path = parts[2..]
+                        .iter()
+                        .cloned()
+                        .chain(unquoted(key_parts(&element)))
+                        .collect::<Vec<String>>();
+                    if let Some(at) = migration.moved.iter().position(|m| path.starts_with(m)) {
+                        let into = migration.into[at];
+                        if let Some(into) = into {
+                            comments.extend(take_comment_lines(&mut kept).into_iter().map(|c| (into, c)));
+                        }
+                        // the line break before the entry goes with it, the one after it now ends the line above
+                        while kept.last().map(SyntaxElement::kind) == Some(WHITESPACE) {
+                            kept.pop();
+                        }
+                        if kept.last().map(SyntaxElement::kind) == Some(NEWLINE) {
+                            kept.pop();
+                        }
+                        // the comments within the entry or at the end of its line
+                        let mut within = element
+                            .as_node()
+                            .unwrap()
+                            .descendants_with_tokens()
+                            .filter(|e| e.kind() == COMMENT)
+                            .map(|e| e.to_string())
+                            .collect::<Vec<String>>();
+                        while elements.peek().map(SyntaxElement::kind) == Some(WHITESPACE) {
+                            elements.next();
+                        }
+                        if elements.peek().map(SyntaxElement::kind) == Some(COMMENT) {
+                            within.push(elements.next().unwrap().to_string());
+                        }
+                        for comment in within {
+                            match into {
+                                Some(into) => comments.push((into, comment)),
+                                // nowhere to go, so these stay on lines of their own
+                                None => kept.extend([make_newline(), make_comment(&comment)]),
+                            }
+                        }
+                        removed = true;
+                        continue;
+                    }
+                }
+                kept.push(element);
+            }
+            if removed {
+                if !kept.iter().any(|e| e.kind() == ENTRY || e.kind() == COMMENT) {
+                    kept.clear();
+                }
+                *table = kept;
+            }
+        }
+    }
+    comments
+}
+
+/// Take the comment lines right above the entry that follows `kept` out of it.
+fn take_comment_lines(kept: &mut Vec<SyntaxElement>) -> Vec<String> {
+    let mut lines = Vec::<String>::new();
+    loop {
+        let mut at = kept.len();
+        while at > 0 && kept[at - 1].kind() == WHITESPACE {
+            at -= 1;
+        }
+        // a blank line separates the comment from the entry
+        if at < 2
+            || kept[at - 1].kind() != NEWLINE
+            || kept[at - 1].to_string().matches('\n').count() != 1
+            || kept[at - 2].kind() != COMMENT
+        {
+            break;
+        }
+        lines.push(kept[at - 2].to_string());
+        kept.truncate(at - 2);
+    }
+    lines.reverse();
+    lines
+}
+
+fn unquoted(parts: Vec<String>) -> Vec<String> {
+    parts
+        .into_iter()
+        .map(|part| String::from(part.trim_matches(['"', '\''])))
+        .collect()
+}
+
+/// A table added after the last one needs that one to end with a line break.
+fn end_with_newline(tables: &Tables) {
+    if let Some(table) = tables.table_set.iter().rev().find(|t| !t.borrow().is_empty()) {
+        let mut table = table.borrow_mut();
+        if table.last().map(SyntaxElement::kind) != Some(NEWLINE) {
+            table.push(make_newline());
+        }
+    }
+}
+
+/// The metadata with a PEP 621 equivalent of the same name, in the order these are added to the project table.
+const METADATA: [&str; 9] = [
+    "name",
+    "version",
+    "description",
+    "readme",
+    "license",
+    "keywords",
+    "classifiers",
+    "authors",
+    "maintainers",
+];
+
+/// The project URLs poetry has a key of its own for, with the label poetry publishes them under.
+const URLS: [(&str, &str); 3] = [
+    ("homepage", "Homepage"),
+    ("repository", "Repository"),
+    ("documentation", "Documentation"),
+];
+
+/// A poetry author or maintainer: `name <email>`.
+static AUTHOR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(.*?)\s*<\s*([^<>]*?)\s*>\s*$").unwrap());
+
+/// A part of a poetry version constraint: the operator, if any, and the version.
+static CONSTRAINT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\^|~=|~|===|==|!=|>=|<=|>|<|=)?([^\^~=!<>]+)$").unwrap());
+
+/// The keys of a dependency given as an inline table, the others follow sorted.
+const INLINE_ORDER: &[&str] = &["version", "extras", "markers", "optional", "source"];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::poetry::{fix, migrate, migration_problems, to_pep440};
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    fn migrated(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        migrate(start, &mut tables);
+        tables.write_back(&root_ast);
+        root_ast.to_string()
+    }
+
+    #[rstest]
+    #[case::no_poetry(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::dependencies(
+        indoc ! {r#"
+    [tool.poetry.dependencies]
+    Requests = "^2.31"
+    # the web framework
+    Flask_Login = { source = "internal", optional = true, version = "^0.6", extras = ["x"] }
+    python = "^3.9"
+    black = [
+      { version = "^23", python = "<3.12" },
+      { python = ">=3.12", version = "^24" },
+    ]
+    attrs.version = "^23"
+    "#},
+        indoc ! {r#"
+    [tool.poetry.dependencies]
+    python = "^3.9"
+    attrs.version = "^23"
+    black = [
+      { version = "^23", python = "<3.12" },
+      { version = "^24", python = ">=3.12" },
+    ]
+    # the web framework
+    flask_login = { version = "^0.6", extras = [
+      "x",
+    ], optional = true, source = "internal" }
+    requests = "^2.31"
+    "#},
+    )]
+    #[case::groups(
+        indoc ! {r#"
+    [tool.poetry]
+    name = "a"
+
+    [tool.poetry.group.test.dependencies]
+    pytest = "^8"
+    coverage = "^7"
+
+    [tool.poetry.group.docs]
+    optional = true
+
+    [tool.poetry.group.docs.dependencies]
+    sphinx = "^7"
+
+    [tool.poetry.scripts]
+    a = "a:main"
+    "#},
+        indoc ! {r#"
+    [tool.poetry]
+    name = "a"
+
+    [tool.poetry.group.docs]
+    optional = true
+
+    [tool.poetry.group.docs.dependencies]
+    sphinx = "^7"
+
+    [tool.poetry.group.test.dependencies]
+    coverage = "^7"
+    pytest = "^8"
+
+    [tool.poetry.scripts]
+    a = "a:main"
+    "#},
+    )]
+    fn test_format_poetry(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+
+    #[rstest]
+    #[case::no_poetry(
+        indoc ! {r#"
+    [project]
+    name = "a"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "a"
+    "#},
+    )]
+    #[case::metadata(
+        indoc ! {r#"
+    [tool.poetry]
+    name = "demo"
+    version = "1.2.0"
+    description = "A demo"
+    readme = "README.md"
+    license = "MIT"
+    keywords = ["demo"]
+    classifiers = ["Private :: Do Not Upload"]
+    authors = ["Jane Doe <jane@example.com>", "John"]
+    maintainers = ["<ops@example.com>"]
+    homepage = "https://example.com"
+    repository = "https://example.com/demo.git"
+    packages = [{ include = "demo" }]
+
+    [tool.poetry.urls]
+    Changelog = "https://example.com/changes"
+
+    [tool.poetry.dependencies]
+    python = "^3.9"
+    requests = { version = "~2.31", extras = ["socks"] }
+    attrs = "*"
+    pyyaml = { version = "^6", optional = true }
+
+    [tool.poetry.extras]
+    yaml = ["PyYAML"]
+
+    [tool.poetry.scripts]
+    demo = "demo.cli:main"
+
+    [build-system]
+    requires = ["poetry-core"]
+    "#},
+        indoc ! {r#"
+    [tool.poetry]
+    packages = [{ include = "demo" }]
+
+    [build-system]
+    requires = ["poetry-core"]
+    [project]
+    name = "demo"
+    version = "1.2.0"
+    description = "A demo"
+    readme = "README.md"
+    license = { text = "MIT" }
+    keywords = ["demo"]
+    classifiers = ["Private :: Do Not Upload"]
+    authors = [{ name = "Jane Doe", email = "jane@example.com" }, { name = "John" }]
+    maintainers = [{ email = "ops@example.com" }]
+    urls.Homepage = "https://example.com"
+    urls.Repository = "https://example.com/demo.git"
+    urls.Changelog = "https://example.com/changes"
+    requires-python = ">=3.9,<4.0"
+    dependencies = ["requests[socks]>=2.31,<2.32", "attrs"]
+    optional-dependencies.yaml = ["pyyaml>=6,<7"]
+    scripts.demo = "demo.cli:main"
+    "#},
+    )]
+    #[case::comments(
+        indoc ! {r#"
+    [tool.poetry]
+    # the distribution
+    name = "demo"
+    version = "1.0" # bumped on release
+
+    [tool.poetry.dependencies]
+    # the runtime
+    requests = "^2.0"
+    numpy = { version = "^1.0", optional = true } # for the fast path
+
+    [tool.poetry.extras]
+    fast = ["numpy"]
+
+    [tool.poetry.scripts]
+    demo = "demo:main" # entry
+    "#},
+        indoc ! {r#"
+    [tool.poetry.dependencies]
+    # for the fast path
+
+    [project]
+    # the distribution
+    name = "demo"
+    # bumped on release
+    version = "1.0"
+    # the runtime
+    dependencies = ["requests>=2.0,<3.0"]
+    optional-dependencies.fast = ["numpy>=1.0,<2.0"]
+    # entry
+    scripts.demo = "demo:main"
+    "#},
+    )]
+    #[case::unrepresentable_stays(
+        indoc ! {r#"
+    [project]
+    name = "demo"
+
+    [tool.poetry]
+    name = "other"
+
+    [tool.poetry.dependencies]
+    a = "^0.2.3"
+    b = { git = "https://example.com/b.git" }
+    c = ">=1 || <0.5"
+    "#},
+        indoc ! {r#"
+    [project]
+    name = "demo"
+    dependencies = ["a>=0.2.3,<0.3.0"]
+
+    [tool.poetry]
+    name = "other"
+
+    [tool.poetry.dependencies]
+    b = { git = "https://example.com/b.git" }
+    c = ">=1 || <0.5"
+    "#},
+    )]
+    fn test_migrate(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(migrated(start), expected);
+    }
+
+    #[rstest]
+    fn test_migration_problems() {
+        let start = indoc! {r#"
+        [project]
+        requires-python = ">=3.9"
+
+        [tool.poetry]
+        readme = ["README.md", "CHANGES.md"]
+        homepage = "https://example.com"
+
+        [tool.poetry.urls]
+        Homepage = "https://example.com/home"
+
+        [tool.poetry.dependencies]
+        python = "^3.9"
+        b = { git = "https://example.com/b.git" }
+        c = [{ version = "^1", python = "<3.12" }, { version = "^2", python = ">=3.12" }]
+        d = { version = "^1", optional = true }
+        e = { version = "^1", optional = true }
+
+        [tool.poetry.extras]
+        x = ["b", "d"]
+
+        [tool.poetry.scripts]
+        f = { reference = "f.sh", type = "file" }
+        "#};
+        let problems = migration_problems(&parse(start).into_dom())
+            .into_iter()
+            .map(|(message, _)| message)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            problems,
+            [
+                "tool.poetry.readme cannot be migrated to the project table: multiple readme files have no PEP 621 \
+                 equivalent",
+                "tool.poetry.urls.Homepage cannot be migrated to the project table: project.urls.Homepage is also set \
+                 by another setting",
+                "tool.poetry.dependencies.python cannot be migrated to the project table: project.requires-python is \
+                 already set",
+                "tool.poetry.dependencies.b cannot be migrated to the project table: the \"git\" key has no PEP 621 \
+                 equivalent",
+                "tool.poetry.dependencies.c cannot be migrated to the project table: multiple constraints have no PEP \
+                 621 equivalent",
+                "tool.poetry.dependencies.e cannot be migrated to the project table: the optional dependency is not \
+                 part of any extra",
+                "tool.poetry.extras.x cannot be migrated to the project table: the dependency \"b\" cannot be migrated",
+                "tool.poetry.scripts.f cannot be migrated to the project table: only scripts given as a reference to \
+                 a function have a PEP 621 equivalent",
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::any("*", Some(""))]
+    #[case::exact("1.2.3", Some("==1.2.3"))]
+    #[case::exact_with_equals("=1.2", Some("==1.2"))]
+    #[case::wildcard("1.2.*", Some("==1.2.*"))]
+    #[case::caret("^1.2.3", Some(">=1.2.3,<2.0.0"))]
+    #[case::caret_major("^1", Some(">=1,<2"))]
+    #[case::caret_zero_major("^0.2.3", Some(">=0.2.3,<0.3.0"))]
+    #[case::caret_zero_minor("^0.0.3", Some(">=0.0.3,<0.0.4"))]
+    #[case::caret_zeros("^0.0", Some(">=0.0,<0.1"))]
+    #[case::tilde("~1.2.3", Some(">=1.2.3,<1.3.0"))]
+    #[case::tilde_major("~1", Some(">=1,<2"))]
+    #[case::compatible("~=1.2", Some("~=1.2"))]
+    #[case::range(">= 1.2, <2", Some(">=1.2,<2"))]
+    #[case::range_by_space(">=1.2 <2 !=1.5", Some(">=1.2,<2,!=1.5"))]
+    #[case::alternatives("^1 || ^2", None)]
+    #[case::dangling_operator(">=", None)]
+    #[case::invalid("^x", None)]
+    fn test_to_pep440(#[case] start: &str, #[case] expected: Option<&str>) {
+        assert_eq!(to_pep440(start), expected.map(String::from));
+    }
+}
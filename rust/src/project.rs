@@ -8,9 +8,10 @@ use taplo::syntax::{SyntaxElement, SyntaxNode};
 use taplo::util::StrExt;
 use taplo::HashSet;
 
+use crate::check::{Normalization, Order};
 use crate::helpers::array::{sort, transform};
 use crate::helpers::create::{make_array, make_array_entry, make_comma, make_entry_of_string, make_newline};
-use crate::helpers::pep508::{format_requirement, get_canonic_requirement_name};
+use crate::helpers::pep508::{format_requirement_or_keep, get_canonic_requirement_name};
 use crate::helpers::string::{load_text, update_content};
 use crate::helpers::table::{collapse_sub_tables, for_entries, reorder_table_keys, Tables};
 
@@ -57,9 +58,9 @@ pub fn fix(
             update_content(entry, |s| s.split_whitespace().collect());
         }
         "dependencies" | "optional-dependencies" => {
-            transform(entry, &|s| format_requirement(s, keep_full_version));
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
             sort(entry, |e| {
-                get_canonic_requirement_name(e).to_lowercase() + " " + &format_requirement(e, keep_full_version)
+                get_canonic_requirement_name(e).to_lowercase() + " " + &format_requirement_or_keep(e, keep_full_version)
             });
         }
         "dynamic" | "keywords" => {
@@ -106,6 +107,41 @@ pub fn fix(
     );
 }
 
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| path == "project.dependencies" || path.starts_with("project.optional-dependencies."),
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "project.name",
+        string: |value, _| get_canonic_requirement_name(value),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "project.description" || path == "project.requires-python",
+        string: |value, _| value.split_whitespace().collect(),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "project.dynamic" || path == "project.keywords",
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    // the Python version classifiers are generated from requires-python
+    Normalization {
+        applies: |path, _| path == "project.classifiers",
+        order: Order::Any,
+        generated: |item| {
+            item.as_str()
+                .is_some_and(|s| s.value().starts_with("Programming Language :: Python :: 3"))
+        },
+        added: true,
+        ..Normalization::NONE
+    },
+];
+
 fn expand_entry_points_inline_tables(table: &mut RefMut<Vec<SyntaxElement>>) {
     let (mut to_insert, mut count, mut key) = (Vec::<SyntaxElement>::new(), 0, String::new());
     for s_table_entry in table.iter() {
@@ -237,7 +273,7 @@ fn generate_classifiers_to_entry(
                                         let mut remove_count = to_insert.len();
                                         for (at, v) in to_insert.iter().rev().enumerate() {
                                             if [COMMA, BRACKET_START].contains(&v.kind()) {
-                                                remove_count = at;
+                                                remove_count = to_insert.len() - at;
                                                 for (i, e) in to_insert.iter().enumerate().skip(to_insert.len() - at) {
                                                     if e.kind() == NEWLINE {
                                                         remove_count = i + 1;
@@ -383,7 +419,7 @@ mod tests {
     fn evaluate(start: &str, keep_full_version: bool, max_supported_python: (u8, u8)) -> String {
         let root_ast = parse(start).into_syntax().clone_for_update();
         let count = root_ast.children_with_tokens().count();
-        let mut tables = Tables::from_ast(&root_ast);
+        let mut tables = Tables::from_ast(&root_ast, false);
         fix(&mut tables, keep_full_version, max_supported_python, (3, 9));
         let entries = tables
             .table_set
@@ -883,6 +919,24 @@ mod tests {
         true,
         (3, 10),
     )]
+    #[case::project_classifier_removed_inline(
+        indoc ! {r#"
+    [project]
+    requires-python = ">=3.12"
+    classifiers = ["Programming Language :: Python :: 3.8", "Programming Language :: Python :: 3.12"]
+    "#},
+        indoc ! {r#"
+    [project]
+    requires-python = ">=3.12"
+    classifiers = [
+      "Programming Language :: Python :: 3 :: Only",
+      "Programming Language :: Python :: 3.12",
+      "Programming Language :: Python :: 3.13",
+    ]
+    "#},
+        true,
+        (3, 13),
+    )]
     fn test_format_project(
         #[case] start: &str,
         #[case] expected: &str,
@@ -0,0 +1,381 @@
+use std::iter::once;
+use std::mem::take;
+
+use taplo::parser::parse;
+use taplo::syntax::{SyntaxElement, SyntaxNode};
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, transform};
+use crate::helpers::create::make_string_array;
+use crate::helpers::table::{for_entries, reorder_table_keys, Tables};
+
+/// Format the pytest configuration, both the `ini_options` table and the native `[tool.pytest]` table of pytest 9.
+/// The filters of `filterwarnings` are only sorted on request as the last matching one wins.
+pub fn fix(tables: &Tables, split_addopts: bool, sort_filterwarnings: bool) {
+    if let Some(table_element) = tables.get("tool.pytest.ini_options") {
+        let table = &mut table_element.first().unwrap().borrow_mut();
+        fix_options(table, split_addopts, sort_filterwarnings);
+        reorder_table_keys(table, &once("").chain(ORDER.iter().copied()).collect::<Vec<&str>>());
+    }
+    if let Some(table_element) = tables.get("tool.pytest") {
+        let table = &mut table_element.first().unwrap().borrow_mut();
+        fix_options(table, split_addopts, sort_filterwarnings);
+        let ini_options = ORDER
+            .iter()
+            .map(|key| format!("ini_options.{key}"))
+            .collect::<Vec<String>>();
+        let order = once("")
+            .chain(ORDER.iter().copied())
+            .chain(ini_options.iter().map(String::as_str))
+            .chain(once("ini_options"))
+            .collect::<Vec<&str>>();
+        reorder_table_keys(table, &order);
+    }
+}
+
+fn fix_options(table: &[SyntaxElement], split_addopts: bool, sort_filterwarnings: bool) {
+    for_entries(
+        table,
+        &mut |key, entry| match key.strip_prefix("ini_options.").unwrap_or(&key) {
+            "addopts" if split_addopts => {
+                split_to_array(entry);
+            }
+            "markers" | "testpaths" | "norecursedirs" | "python_files" => {
+                transform(entry, &|s| String::from(s));
+                sort(entry, str::to_lowercase);
+            }
+            "filterwarnings" => {
+                transform(entry, &|s| String::from(s));
+                if sort_filterwarnings {
+                    sort(entry, str::to_lowercase);
+                }
+            }
+            _ => {}
+        },
+    );
+}
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, opt| opt.split_pytest_addopts && option(path) == Some("addopts"),
+        split: Some(split_args),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, opt| {
+            option(path).is_some_and(|key| {
+                ["markers", "testpaths", "norecursedirs", "python_files"].contains(&key)
+                    || (opt.sort_pytest_filterwarnings && key == "filterwarnings")
+            })
+        },
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
+/// The name of the option at the dotted path, when within one of the pytest tables.
+fn option(path: &str) -> Option<&str> {
+    path.strip_prefix("tool.pytest.ini_options.")
+        .or_else(|| path.strip_prefix("tool.pytest."))
+}
+
+/// Replace a string value with the array of arguments pytest splits it into.
+fn split_to_array(entry: &SyntaxNode) {
+    let text = format!("a = {}", entry.text());
+    let Some(value) = parse(&text)
+        .into_dom()
+        .as_table()
+        .and_then(|t| t.get("a"))
+        .and_then(|a| a.as_str().map(|s| String::from(s.value())))
+    else {
+        return;
+    };
+    let Some(args) = split_args(&value) else {
+        return;
+    };
+    let array = make_string_array(&args);
+    let count = entry.children_with_tokens().count();
+    entry.splice_children(0..count, array.as_node().unwrap().children_with_tokens().collect());
+}
+
+/// Split a command line the way pytest does for a string `addopts` (the POSIX mode of Python's `shlex.split`), `None`
+/// for unbalanced quotes. Unlike a shell, only a backslash or a double quote can be escaped within double quotes.
+pub fn split_args(text: &str) -> Option<Vec<String>> {
+    let (mut args, mut current, mut in_arg) = (Vec::<String>::new(), String::new(), false);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => current.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = chars.next()?;
+                            if !['\\', '"'].contains(&escaped) {
+                                current.push('\\');
+                            }
+                            current.push(escaped);
+                        }
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                current.push(chars.next()?);
+            }
+            ' ' | '\t' | '\r' | '\n' => {
+                if in_arg {
+                    args.push(take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Some(args)
+}
+
+/// The ini options: requirements and arguments first, then test discovery, test behavior, logging and reports.
+const ORDER: &[&str] = &[
+    "minversion",
+    "required_plugins",
+    "addopts",
+    "testpaths",
+    "pythonpath",
+    "norecursedirs",
+    "python_files",
+    "python_classes",
+    "python_functions",
+    "consider_namespace_packages",
+    "markers",
+    "empty_parameter_set_mark",
+    "usefixtures",
+    "filterwarnings",
+    "xfail_strict",
+    "strict",
+    "faulthandler_timeout",
+    "cache_dir",
+    "tmp_path_retention_count",
+    "tmp_path_retention_policy",
+    "console_output_style",
+    "verbosity_assertions",
+    "verbosity_test_cases",
+    "enable_assertion_pass_hook",
+    "doctest_optionflags",
+    "doctest_encoding",
+    "log_level",
+    "log_format",
+    "log_date_format",
+    "log_auto_indent",
+    "log_cli",
+    "log_cli_level",
+    "log_cli_format",
+    "log_cli_date_format",
+    "log_file",
+    "log_file_mode",
+    "log_file_level",
+    "log_file_format",
+    "log_file_date_format",
+    "junit_suite_name",
+    "junit_family",
+    "junit_logging",
+    "junit_log_passing_tests",
+    "junit_duration_report",
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::pytest::{fix, split_args};
+
+    fn evaluate(start: &str, split_addopts: bool, sort_filterwarnings: bool) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let tables = Tables::from_ast(&root_ast, false);
+        fix(&tables, split_addopts, sort_filterwarnings);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_pytest(
+        indoc ! {r""},
+        "\n",
+        false,
+        false,
+    )]
+    #[case::ini_options(
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    xfail_strict = true
+    filterwarnings = ["ignore::DeprecationWarning", "error"]
+    markers = ["slow: takes long", "network: needs a connection"]
+    testpaths = ["tests", "docs"]
+    addopts = "-ra --strict-markers"
+    minversion = "8"
+    "#},
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    minversion = "8"
+    addopts = "-ra --strict-markers"
+    testpaths = [
+      "docs",
+      "tests",
+    ]
+    markers = [
+      "network: needs a connection",
+      "slow: takes long",
+    ]
+    filterwarnings = [
+      "ignore::DeprecationWarning",
+      "error",
+    ]
+    xfail_strict = true
+    "#},
+        false,
+        false,
+    )]
+    #[case::sort_filterwarnings(
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    filterwarnings = ["ignore::DeprecationWarning", "error"]
+    "#},
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    filterwarnings = [
+      "error",
+      "ignore::DeprecationWarning",
+    ]
+    "#},
+        false,
+        true,
+    )]
+    #[case::split_addopts(
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    addopts = """
+      -ra
+      -p no:cacheprovider --tb='short'
+    """
+    "#},
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    addopts = [
+      "-ra",
+      "-p",
+      "no:cacheprovider",
+      "--tb=short",
+    ]
+    "#},
+        true,
+        false,
+    )]
+    #[case::split_addopts_unbalanced_quotes(
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    addopts = "-k 'a"
+    "#},
+        indoc ! {r#"
+    [tool.pytest.ini_options]
+    addopts = "-k 'a"
+    "#},
+        true,
+        false,
+    )]
+    #[case::native(
+        indoc ! {r#"
+    [tool.pytest]
+    python_files = ["test_*.py", "check_*.py"]
+    addopts = "-ra"
+    minversion = "9.0"
+    "#},
+        indoc ! {r#"
+    [tool.pytest]
+    minversion = "9.0"
+    addopts = [
+      "-ra",
+    ]
+    python_files = [
+      "check_*.py",
+      "test_*.py",
+    ]
+    "#},
+        true,
+        false,
+    )]
+    #[case::dotted_ini_options(
+        indoc ! {r#"
+    [tool.pytest]
+    ini_options.norecursedirs = ["b", "a"]
+    ini_options.minversion = "8"
+    "#},
+        indoc ! {r#"
+    [tool.pytest]
+    ini_options.minversion = "8"
+    ini_options.norecursedirs = [
+      "a",
+      "b",
+    ]
+    "#},
+        false,
+        false,
+    )]
+    fn test_format_pytest(
+        #[case] start: &str,
+        #[case] expected: &str,
+        #[case] split_addopts: bool,
+        #[case] sort_filterwarnings: bool,
+    ) {
+        assert_eq!(evaluate(start, split_addopts, sort_filterwarnings), expected);
+    }
+
+    #[rstest]
+    #[case::empty("", Some(vec![]))]
+    #[case::words(" -x  -v\n", Some(vec!["-x", "-v"]))]
+    #[case::single_quotes("-k 'a or b'", Some(vec!["-k", "a or b"]))]
+    #[case::double_quotes(r#"-k "a \"b\" \c""#, Some(vec!["-k", r#"a "b" \c"#]))]
+    #[case::escaped_space(r"a\ b", Some(vec!["a b"]))]
+    #[case::dollar_in_double_quotes(r#"-o "cache_dir=\$HOME""#, Some(vec!["-o", r"cache_dir=\$HOME"]))]
+    #[case::newline_in_double_quotes("\"a\\\nb\"", Some(vec!["a\\\nb"]))]
+    #[case::escaped_newline("a\\\nb", Some(vec!["a\nb"]))]
+    #[case::no_break_space("a\u{a0}b", Some(vec!["a\u{a0}b"]))]
+    #[case::unbalanced("-k 'a", None)]
+    fn test_split_args(#[case] start: &str, #[case] expected: Option<Vec<&str>>) {
+        assert_eq!(
+            split_args(start),
+            expected.map(|args| args.into_iter().map(String::from).collect())
+        );
+    }
+}
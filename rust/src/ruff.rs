@@ -1,3 +1,4 @@
+use crate::check::{Normalization, Order};
 use crate::helpers::array::{sort, transform};
 use crate::helpers::string::update_content;
 use crate::helpers::table::{collapse_sub_tables, for_entries, reorder_table_keys, Tables};
@@ -35,62 +36,7 @@ pub fn fix(tables: &mut Tables) {
         | "lint.pydocstyle.convention" => {
             update_content(entry, |s| String::from(s));
         }
-        "exclude"
-        | "extend-exclude"
-        | "builtins"
-        | "include"
-        | "extend-include"
-        | "namespace-packages"
-        | "src"
-        | "format.exclude"
-        | "lint.allowed-confusables"
-        | "lint.exclude"
-        | "lint.extend-fixable"
-        | "lint.extend-ignore"
-        | "lint.extend-safe-fixes"
-        | "lint.extend-select"
-        | "lint.extend-unsafe-fixes"
-        | "lint.external"
-        | "lint.fixable"
-        | "lint.ignore"
-        | "lint.logger-objects"
-        | "lint.select"
-        | "lint.task-tags"
-        | "lint.typing-modules"
-        | "lint.unfixable"
-        | "lint.flake8-bandit.hardcoded-tmp-directory"
-        | "lint.flake8-bandit.hardcoded-tmp-directory-extend"
-        | "lint.flake8-boolean-trap.extend-allowed-calls"
-        | "lint.flake8-bugbear.extend-immutable-calls"
-        | "lint.flake8-builtins.builtins-ignorelist"
-        | "lint.flake8-gettext.extend-function-names"
-        | "lint.flake8-gettext.function-names"
-        | "lint.flake8-import-conventions.banned-from"
-        | "lint.flake8-pytest-style.raises-extend-require-match-for"
-        | "lint.flake8-pytest-style.raises-require-match-for"
-        | "lint.flake8-self.extend-ignore-names"
-        | "lint.flake8-self.ignore-names"
-        | "lint.flake8-tidy-imports.banned-module-level-imports"
-        | "lint.flake8-type-checking.exempt-modules"
-        | "lint.flake8-type-checking.runtime-evaluated-base-classes"
-        | "lint.flake8-type-checking.runtime-evaluated-decorators"
-        | "lint.isort.constants"
-        | "lint.isort.default-section"
-        | "lint.isort.extra-standard-library"
-        | "lint.isort.forced-separate"
-        | "lint.isort.no-lines-before"
-        | "lint.isort.required-imports"
-        | "lint.isort.single-line-exclusions"
-        | "lint.isort.variables"
-        | "lint.pep8-naming.classmethod-decorators"
-        | "lint.pep8-naming.extend-ignore-names"
-        | "lint.pep8-naming.ignore-names"
-        | "lint.pep8-naming.staticmethod-decorators"
-        | "lint.pydocstyle.ignore-decorators"
-        | "lint.pydocstyle.property-decorators"
-        | "lint.pyflakes.extend-generics"
-        | "lint.pylint.allow-dunder-method-names"
-        | "lint.pylint.allow-magic-value-types" => {
+        name if SORTED.contains(&name) => {
             transform(entry, &|s| String::from(s));
             sort(entry, str::to_lowercase);
         }
@@ -188,11 +134,84 @@ pub fn fix(tables: &mut Tables) {
     );
 }
 
+/// The settings holding lists, which ruff treats as sets.
+const SORTED: &[&str] = &[
+    "exclude",
+    "extend-exclude",
+    "builtins",
+    "include",
+    "extend-include",
+    "namespace-packages",
+    "src",
+    "format.exclude",
+    "lint.allowed-confusables",
+    "lint.exclude",
+    "lint.extend-fixable",
+    "lint.extend-ignore",
+    "lint.extend-safe-fixes",
+    "lint.extend-select",
+    "lint.extend-unsafe-fixes",
+    "lint.external",
+    "lint.fixable",
+    "lint.ignore",
+    "lint.logger-objects",
+    "lint.select",
+    "lint.task-tags",
+    "lint.typing-modules",
+    "lint.unfixable",
+    "lint.flake8-bandit.hardcoded-tmp-directory",
+    "lint.flake8-bandit.hardcoded-tmp-directory-extend",
+    "lint.flake8-boolean-trap.extend-allowed-calls",
+    "lint.flake8-bugbear.extend-immutable-calls",
+    "lint.flake8-builtins.builtins-ignorelist",
+    "lint.flake8-gettext.extend-function-names",
+    "lint.flake8-gettext.function-names",
+    "lint.flake8-import-conventions.banned-from",
+    "lint.flake8-pytest-style.raises-extend-require-match-for",
+    "lint.flake8-pytest-style.raises-require-match-for",
+    "lint.flake8-self.extend-ignore-names",
+    "lint.flake8-self.ignore-names",
+    "lint.flake8-tidy-imports.banned-module-level-imports",
+    "lint.flake8-type-checking.exempt-modules",
+    "lint.flake8-type-checking.runtime-evaluated-base-classes",
+    "lint.flake8-type-checking.runtime-evaluated-decorators",
+    "lint.isort.constants",
+    "lint.isort.default-section",
+    "lint.isort.extra-standard-library",
+    "lint.isort.forced-separate",
+    "lint.isort.no-lines-before",
+    "lint.isort.required-imports",
+    "lint.isort.single-line-exclusions",
+    "lint.isort.variables",
+    "lint.pep8-naming.classmethod-decorators",
+    "lint.pep8-naming.extend-ignore-names",
+    "lint.pep8-naming.ignore-names",
+    "lint.pep8-naming.staticmethod-decorators",
+    "lint.pydocstyle.ignore-decorators",
+    "lint.pydocstyle.property-decorators",
+    "lint.pyflakes.extend-generics",
+    "lint.pylint.allow-dunder-method-names",
+    "lint.pylint.allow-magic-value-types",
+];
+
+pub const NORMALIZATIONS: &[Normalization] = &[Normalization {
+    applies: |path, _| {
+        path.strip_prefix("tool.ruff.").is_some_and(|key| {
+            SORTED.contains(&key)
+                || key.starts_with("lint.extend-per-file-ignores.")
+                || key.starts_with("lint.per-file-ignores.")
+        })
+    },
+    order: Order::Any,
+    ..Normalization::NONE
+}];
+
 #[cfg(test)]
 mod tests {
     use std::fs::read_to_string;
     use std::path::{Path, PathBuf};
 
+    use indoc::indoc;
     use rstest::{fixture, rstest};
     use taplo::formatter::{format_syntax, Options};
     use taplo::parser::parse;
@@ -204,7 +223,7 @@ mod tests {
     fn evaluate(start: &str) -> String {
         let root_ast = parse(start).into_syntax().clone_for_update();
         let count = root_ast.children_with_tokens().count();
-        let mut tables = Tables::from_ast(&root_ast);
+        let mut tables = Tables::from_ast(&root_ast, false);
         fix(&mut tables);
         let entries = tables
             .table_set
@@ -241,4 +260,55 @@ mod tests {
         let expected = read_to_string(data.join("ruff-21.expected.toml")).unwrap();
         assert_eq!(got, expected);
     }
+
+    #[rstest]
+    #[case::section_comments(
+        indoc ! {r#"
+    [tool.ruff]
+    # shared by all projects
+
+    line-length = 120
+    target-version = "py39"
+
+    # --- linting ---
+
+    # no docstrings
+    lint.ignore = ["D"]
+    lint.select = ["ALL"]
+    "#},
+        indoc ! {r#"
+    [tool.ruff]
+    # shared by all projects
+
+    target-version = "py39"
+    line-length = 120
+
+    # --- linting ---
+
+    lint.select = [
+      "ALL",
+    ]
+    # no docstrings
+    lint.ignore = [
+      "D",
+    ]
+    "#},
+    )]
+    #[case::comment_above_key(
+        indoc ! {r#"
+    [tool.ruff]
+    # wide screens
+    line-length = 120
+    target-version = "py39"
+    "#},
+        indoc ! {r#"
+    [tool.ruff]
+    target-version = "py39"
+    # wide screens
+    line-length = 120
+    "#},
+    )]
+    fn test_ruff_comments(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
 }
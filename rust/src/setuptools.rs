@@ -0,0 +1,258 @@
+use taplo::dom::Node;
+use taplo::syntax::SyntaxKind::{ENTRY, INLINE_TABLE, VALUE};
+use taplo::syntax::SyntaxNode;
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, transform};
+use crate::helpers::dom::{lookup, strings};
+use crate::helpers::table::{collapse_sub_tables, for_entries, reorder_inline_table_keys, reorder_table_keys, Tables};
+
+pub fn fix(tables: &mut Tables) {
+    collapse_sub_tables(tables, "tool.setuptools");
+    let Some(table_element) = tables.get("tool.setuptools") else {
+        return;
+    };
+    let table = &mut table_element.first().unwrap().borrow_mut();
+    for_entries(table, &mut |key, entry| {
+        let name = key.split('.').next().unwrap_or_default();
+        if SORTED.contains(&name) {
+            sort_paths(entry);
+        }
+        if PER_PACKAGE.contains(&name) {
+            reorder_inline_table_keys(entry, &[]);
+        }
+    });
+    // the keys of the package data are sorted, which puts the "*" (all packages) key first
+    reorder_table_keys(table, ORDER);
+}
+
+/// Sort the strings of the arrays, also within (nested) inline tables. These name packages, modules or paths, so the
+/// case is kept.
+fn sort_paths(entry: &SyntaxNode) {
+    transform(entry, &|s| String::from(s));
+    sort(entry, str::to_lowercase);
+    for inline_table in entry.children().filter(|n| n.kind() == INLINE_TABLE) {
+        for value in inline_table
+            .children()
+            .filter(|n| n.kind() == ENTRY)
+            .flat_map(|n| n.children().filter(|c| c.kind() == VALUE))
+        {
+            sort_paths(&value);
+        }
+    }
+}
+
+/// The entries of `tool.setuptools.dynamic` for fields not declared dynamic by `project.dynamic`, which setuptools
+/// refuses to use, with the message to report for each.
+pub fn dynamic_problems(root: &Node) -> Vec<(String, Node)> {
+    let Some(Node::Table(dynamic)) = lookup(root, &["tool", "setuptools", "dynamic"]) else {
+        return vec![];
+    };
+    if lookup(root, &["project"]).is_none() {
+        return vec![];
+    }
+    let declared = strings(lookup(root, &["project", "dynamic"]).as_ref())
+        .into_iter()
+        .map(|(field, _)| field)
+        .collect::<Vec<String>>();
+    let mut problems = Vec::new();
+    for (key, value) in dynamic.entries().read().iter() {
+        let field = key.value();
+        // the entry points also provide the scripts
+        let fields: &[&str] = if field == "entry-points" {
+            &["entry-points", "scripts", "gui-scripts"]
+        } else {
+            &[field]
+        };
+        if !fields.iter().any(|f| declared.iter().any(|d| d == f)) {
+            problems.push((
+                format!("tool.setuptools.dynamic.{field} is set but {field} is not listed in project.dynamic"),
+                value.clone(),
+            ));
+        }
+    }
+    problems
+}
+
+/// The settings holding lists of packages, modules or paths (also per package), whose order carries no meaning.
+const SORTED: &[&str] = &[
+    "packages",
+    "py-modules",
+    "script-files",
+    "license-files",
+    "namespace-packages",
+    "package-data",
+    "exclude-package-data",
+    "data-files",
+];
+
+/// The settings holding a table by package (or directory).
+const PER_PACKAGE: &[&str] = &["package-data", "exclude-package-data", "data-files"];
+
+pub const NORMALIZATIONS: &[Normalization] = &[Normalization {
+    applies: |path, _| {
+        path.strip_prefix("tool.setuptools.")
+            .is_some_and(|key| SORTED.contains(&key.split('.').next().unwrap_or_default()))
+    },
+    order: Order::Any,
+    ..Normalization::NONE
+}];
+
+const ORDER: &[&str] = &[
+    "",
+    "platforms",
+    "provides",
+    "obsoletes",
+    "zip-safe",
+    "script-files",
+    "eager-resources",
+    "packages",
+    "package-dir",
+    "package-data",
+    "include-package-data",
+    "exclude-package-data",
+    "namespace-packages",
+    "py-modules",
+    "ext-modules",
+    "data-files",
+    "cmdclass",
+    "license-files",
+    "dynamic",
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::setuptools::{dynamic_problems, fix};
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_setuptools(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::sub_tables(
+        indoc ! {r#"
+    [tool.setuptools]
+    py-modules = ["b", "a"]
+    include-package-data = true
+
+    [tool.setuptools.packages.find]
+    where = ["src"]
+    exclude = ["tests*", "docs*"]
+
+    [tool.setuptools.package-data]
+    pkg = ["data/*.json", "*.txt"]
+    "*" = ["py.typed"]
+
+    [tool.setuptools.dynamic]
+    version = { attr = "pkg.__version__" }
+    "#},
+        indoc ! {r#"
+    [tool.setuptools]
+    packages.find.exclude = [
+      "docs*",
+      "tests*",
+    ]
+    packages.find.where = [
+      "src",
+    ]
+    package-data."*" = [
+      "py.typed",
+    ]
+    package-data.pkg = [
+      "*.txt",
+      "data/*.json",
+    ]
+    include-package-data = true
+    py-modules = [
+      "a",
+      "b",
+    ]
+    dynamic.version = { attr = "pkg.__version__" }
+    "#},
+    )]
+    #[case::inline_tables(
+        indoc ! {r#"
+    [tool.setuptools]
+    package-data = { pkg = ["b.txt", "a.txt"], "*" = ["py.typed"] }
+    packages = { find = { include = ["pkg*", "app*"] } }
+    "#},
+        indoc ! {r#"
+    [tool.setuptools]
+    packages = { find = { include = [
+      "app*",
+      "pkg*",
+    ] } }
+    package-data = { "*" = [
+      "py.typed",
+    ], pkg = [
+      "a.txt",
+      "b.txt",
+    ] }
+    "#},
+    )]
+    fn test_format_setuptools(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+
+    #[rstest]
+    #[case::matching(
+        indoc ! {r#"
+    [project]
+    dynamic = ["version", "scripts"]
+    [tool.setuptools.dynamic]
+    version = { attr = "a.__version__" }
+    entry-points = { file = "entry_points.txt" }
+    "#},
+        &[],
+    )]
+    #[case::not_declared(
+        indoc ! {r#"
+    [project]
+    dynamic = ["version"]
+    [tool.setuptools]
+    dynamic.version = { attr = "a.__version__" }
+    dynamic.readme = { file = "README.md" }
+    "#},
+        &["tool.setuptools.dynamic.readme is set but readme is not listed in project.dynamic"],
+    )]
+    #[case::no_project(
+        indoc ! {r#"
+    [tool.setuptools.dynamic]
+    readme = { file = "README.md" }
+    "#},
+        &[],
+    )]
+    fn test_dynamic_problems(#[case] start: &str, #[case] expected: &[&str]) {
+        let root = parse(start).into_dom();
+        let got = dynamic_problems(&root)
+            .into_iter()
+            .map(|(message, _)| message)
+            .collect::<Vec<String>>();
+        assert_eq!(got, expected);
+    }
+}
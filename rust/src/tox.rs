@@ -0,0 +1,323 @@
+use taplo::syntax::SyntaxNode;
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, string_values, transform};
+use crate::helpers::pep508::format_requirement_or_keep;
+use crate::helpers::table::{
+    collapse_tables_where, for_entries, reorder_inline_table_keys, reorder_table_keys, reorder_tables, split_name,
+    Tables,
+};
+
+pub fn fix(tables: &mut Tables, keep_full_version: bool) {
+    // each environment is a single table, its sub-tables (e.g. set_env) become dotted keys of it
+    collapse_tables_where(tables, |name| {
+        let parts = split_name(name);
+        parts.len() > env_depth(&parts).unwrap_or(usize::MAX)
+    });
+    let mut env_list = Vec::<String>::new();
+    if let Some(table_element) = tables.get("tool.tox") {
+        let table = &mut table_element.first().unwrap().borrow_mut();
+        for_entries(table, &mut |key, entry| {
+            if key == "env_list" {
+                env_list = string_values(entry);
+            }
+            fix_env("tool.tox", &key, entry, keep_full_version);
+        });
+        reorder_table_keys(table, ROOT_ORDER);
+    }
+    let mut envs = tables
+        .header_to_pos
+        .iter()
+        .filter(|(name, positions)| {
+            let parts = split_name(name);
+            positions.len() == 1 && env_depth(&parts) == Some(parts.len())
+        })
+        .map(|(name, positions)| (positions[0], name.clone()))
+        .collect::<Vec<(usize, String)>>();
+    envs.sort();
+    for (position, name) in &envs {
+        let table = &mut tables.table_set[*position].borrow_mut();
+        for_entries(table, &mut |key, entry| fix_env(name, &key, entry, keep_full_version));
+        reorder_table_keys(table, &[&[""], ENV_ORDER].concat());
+    }
+    order_envs(tables, &envs, &env_list);
+}
+
+/// The depth of the environment tables `parts` belongs to: `tool.tox.env_run_base`, `tool.tox.env_pkg_base` and
+/// `tool.tox.env.<name>`.
+fn env_depth(parts: &[String]) -> Option<usize> {
+    match parts {
+        [tool, tox, base, ..]
+            if tool == "tool" && tox == "tox" && (base == "env_run_base" || base == "env_pkg_base") =>
+        {
+            Some(3)
+        }
+        [tool, tox, env, _, ..] if tool == "tool" && tox == "tox" && env == "env" => Some(4),
+        _ => None,
+    }
+}
+
+/// Fix the setting `key` of the table `table`, when it is one of an environment (e.g. `deps`, but not `set_env.deps`).
+fn fix_env(table: &str, key: &str, entry: &SyntaxNode, keep_full_version: bool) {
+    let parts = [split_name(table), split_name(key)].concat();
+    let Some(depth) = env_depth(&parts) else {
+        return;
+    };
+    match parts[depth..].join(".").as_str() {
+        "deps" => {
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
+        }
+        "pass_env" => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        "set_env" => {
+            reorder_inline_table_keys(entry, &[]);
+        }
+        // the arguments of commands are positional, these are never reordered
+        _ => {}
+    }
+}
+
+/// Order the `tool.tox.env.<name>` tables as listed by `env_list`, the others follow in their original order.
+fn order_envs(tables: &mut Tables, envs: &[(usize, String)], env_list: &[String]) {
+    let mut named = envs
+        .iter()
+        .filter(|(_, name)| name.starts_with("tool.tox.env."))
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<String>>();
+    named.sort_by_cached_key(|name| {
+        let env = split_name(name).pop().unwrap_or_default();
+        let env = env.trim_matches(['"', '\'']);
+        env_list.iter().position(|e| e == env).unwrap_or(env_list.len())
+    });
+    reorder_tables(tables, &named);
+}
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| env_setting(path) == Some("deps"),
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| env_setting(path) == Some("pass_env"),
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+];
+
+/// The key of the setting at the dotted path relative to its environment table. The name of an environment may hold
+/// dots (e.g. `py3.12`), so the key is the longest tail of the path starting with a setting.
+fn env_setting(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix("tool.tox.")?;
+    if let Some(setting) = rest
+        .strip_prefix("env_run_base.")
+        .or_else(|| rest.strip_prefix("env_pkg_base."))
+    {
+        return Some(setting);
+    }
+    let rest = rest.strip_prefix("env.")?;
+    rest.match_indices('.')
+        .map(|(at, _)| &rest[at + 1..])
+        .find(|setting| ENV_ORDER.contains(&setting.split('.').next().unwrap_or_default()))
+}
+
+const ROOT_ORDER: &[&str] = &[
+    "",
+    "requires",
+    "min_version",
+    "provision_tox_env",
+    "env_list",
+    "labels",
+    "skip_missing_interpreters",
+    "no_package",
+    "tox_root",
+    "work_dir",
+    "temp_dir",
+    "env_run_base",
+    "env_pkg_base",
+    "env",
+];
+
+const ENV_ORDER: &[&str] = &[
+    "description",
+    "base",
+    "runner",
+    "base_python",
+    "package",
+    "package_env",
+    "wheel_build_env",
+    "skip_install",
+    "use_develop",
+    "extras",
+    "dependency_groups",
+    "deps",
+    "constraints",
+    "pass_env",
+    "set_env",
+    "change_dir",
+    "allowlist_externals",
+    "depends",
+    "labels",
+    "parallel_show_output",
+    "commands_pre",
+    "commands",
+    "commands_post",
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::tox::fix;
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let mut tables = Tables::from_ast(&root_ast, false);
+        fix(&mut tables, false);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_tox(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::run_base(
+        indoc ! {r#"
+    [tool.tox]
+    env_list = ["3.13"]
+    requires = ["tox>=4.19"]
+
+    [tool.tox.env_run_base]
+    commands = [["pytest", "-x", { replace = "posargs", default = ["tests"], extend = true }], ["coverage", "report"]]
+    set_env = { PYTHONHASHSEED = "0", COVERAGE_FILE = "x" }
+    pass_env = ["PYTEST_*", "CI"]
+    deps = ["pytest>=8.0", "-r requirements.txt"]
+    description = "run the tests"
+    "#},
+        indoc ! {r#"
+    [tool.tox]
+    requires = [
+      "tox>=4.19",
+    ]
+    env_list = [
+      "3.13",
+    ]
+
+    [tool.tox.env_run_base]
+    description = "run the tests"
+    deps = [
+      "pytest>=8",
+      "-r requirements.txt",
+    ]
+    pass_env = [
+      "CI",
+      "PYTEST_*",
+    ]
+    set_env = { COVERAGE_FILE = "x", PYTHONHASHSEED = "0" }
+    commands = [
+      [
+        "pytest",
+        "-x",
+        { replace = "posargs", default = [
+          "tests",
+        ], extend = true },
+      ],
+      [
+        "coverage",
+        "report",
+      ],
+    ]
+    "#},
+    )]
+    #[case::envs_follow_env_list(
+        indoc ! {r#"
+    [tool.tox]
+    env_list = ["type", "lint", "3.13"]
+
+    [tool.tox.env.lint]
+    skip_install = true
+
+    [tool.tox.env.lint.set_env]
+    B = "1"
+    A = "2"
+
+    [tool.tox.env.other]
+    skip_install = true
+
+    [tool.tox.env."3.13"]
+    base_python = "3.13"
+
+    [tool.tox.env.type]
+    deps = ["mypy==1.11.0"]
+    "#},
+        indoc ! {r#"
+    [tool.tox]
+    env_list = [
+      "type",
+      "lint",
+      "3.13",
+    ]
+
+    [tool.tox.env.type]
+    deps = [
+      "mypy==1.11",
+    ]
+
+    [tool.tox.env.lint]
+    skip_install = true
+    set_env.A = "2"
+    set_env.B = "1"
+
+    [tool.tox.env."3.13"]
+    base_python = "3.13"
+
+    [tool.tox.env.other]
+    skip_install = true
+    "#},
+    )]
+    #[case::nested_keys(
+        indoc ! {r#"
+    [tool.tox.env."py3.12"]
+    deps = ["B==1.0.0", "A"]
+    set_env.deps = ["B==1.0.0", "A"]
+    set_env.pass_env = ["B", "A"]
+    "#},
+        indoc ! {r#"
+    [tool.tox.env."py3.12"]
+    deps = [
+      "b==1",
+      "a",
+    ]
+    set_env.deps = [
+      "B==1.0.0",
+      "A",
+    ]
+    set_env.pass_env = [
+      "B",
+      "A",
+    ]
+    "#},
+    )]
+    fn test_format_tox(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+}
@@ -0,0 +1,256 @@
+use std::cell::RefMut;
+use std::iter::{once, zip};
+
+use taplo::dom::Node;
+use taplo::syntax::SyntaxElement;
+use taplo::syntax::SyntaxKind::{BOOL, ENTRY, NEWLINE, TABLE_ARRAY_HEADER};
+
+use crate::check::{Normalization, Order};
+use crate::helpers::array::{sort, transform};
+use crate::helpers::create::make_newline;
+use crate::helpers::pep508::{format_requirement_or_keep, get_canonic_package_name, get_canonic_requirement_name};
+use crate::helpers::table::{for_entries, header_of, key_parts, reorder_table_keys, Tables};
+
+pub fn fix(tables: &Tables, keep_full_version: bool) {
+    // the workspace settings may be dotted keys of the uv table or keys of their own table
+    for (name, prefix) in [("tool.uv", ""), ("tool.uv.workspace", "workspace.")] {
+        if let Some(table_element) = tables.get(name) {
+            fix_settings(&table_element.first().unwrap().borrow(), prefix, keep_full_version);
+        }
+    }
+    if let Some(table_element) = tables.get("tool.uv.sources") {
+        sort_sources(&mut table_element.first().unwrap().borrow_mut());
+    }
+    order_indexes(tables);
+}
+
+fn fix_settings(table: &[SyntaxElement], prefix: &str, keep_full_version: bool) {
+    for_entries(table, &mut |key, entry| match format!("{prefix}{key}").as_str() {
+        // may also hold installer options and paths (e.g. `-e ./a`), these are kept as written
+        "dev-dependencies" | "constraint-dependencies" | "override-dependencies" => {
+            transform(entry, &|s| format_requirement_or_keep(s, keep_full_version));
+            sort(entry, |e| {
+                get_canonic_requirement_name(e).to_lowercase() + " " + &format_requirement_or_keep(e, keep_full_version)
+            });
+        }
+        "workspace.members" | "workspace.exclude" => {
+            transform(entry, &|s| String::from(s));
+            sort(entry, str::to_lowercase);
+        }
+        _ => {}
+    });
+}
+
+/// Order the sources by the canonical name of the package they are for.
+fn sort_sources(table: &mut RefMut<Vec<SyntaxElement>>) {
+    let mut packages = Vec::<String>::new();
+    for element in table.iter().filter(|e| e.kind() == ENTRY) {
+        if let Some(package) = key_parts(element).into_iter().next() {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    packages.sort_by_cached_key(|package| get_canonic_package_name(package.trim_matches(['"', '\''])));
+    let order = once("")
+        .chain(packages.iter().map(String::as_str))
+        .collect::<Vec<&str>>();
+    reorder_table_keys(table, &order);
+}
+
+/// uv searches the indexes in the order they are declared, except for the default one that is always searched last, so
+/// only that one moves: to the end, where it takes effect.
+fn order_indexes(tables: &Tables) {
+    let Some(positions) = tables.header_to_pos.get("tool.uv.index") else {
+        return;
+    };
+    if positions
+        .iter()
+        .any(|p| header_of(&tables.table_set[*p].borrow()).map(SyntaxElement::kind) != Some(TABLE_ARRAY_HEADER))
+    {
+        return;
+    }
+    let (mut indexes, mut separators) = (Vec::<(bool, Vec<SyntaxElement>)>::new(), Vec::new());
+    for position in positions {
+        let mut element = tables.table_set[*position].borrow_mut();
+        let mut default = false;
+        for_entries(&element, &mut |key, entry| {
+            if key == "default" {
+                default = entry
+                    .children_with_tokens()
+                    .any(|e| e.kind() == BOOL && e.as_token().unwrap().text() == "true");
+            }
+        });
+        // the blank lines after a table separate it from the next one, so they stay in place
+        let separator = match element.last() {
+            Some(last) if last.kind() == NEWLINE => element.pop().unwrap(),
+            _ => make_newline(),
+        };
+        separators.push(separator);
+        indexes.push((default, element.clone()));
+    }
+    indexes.sort_by_key(|(default, _)| *default);
+    for ((position, (_, mut element)), separator) in zip(zip(positions, indexes), separators) {
+        element.push(separator);
+        tables.table_set[*position].replace(element);
+    }
+}
+
+pub const NORMALIZATIONS: &[Normalization] = &[
+    Normalization {
+        applies: |path, _| {
+            matches!(
+                path,
+                "tool.uv.dev-dependencies" | "tool.uv.constraint-dependencies" | "tool.uv.override-dependencies"
+            )
+        },
+        string: |value, opt| format_requirement_or_keep(value, opt.keep_full_version),
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "tool.uv.workspace.members" || path == "tool.uv.workspace.exclude",
+        order: Order::Any,
+        ..Normalization::NONE
+    },
+    Normalization {
+        applies: |path, _| path == "tool.uv.index",
+        order: Order::Last(
+            |index| matches!(index.as_table().and_then(|t| t.get("default")), Some(Node::Bool(default)) if default.value()),
+        ),
+        ..Normalization::NONE
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+    use taplo::formatter::{format_syntax, Options};
+    use taplo::parser::parse;
+    use taplo::syntax::SyntaxElement;
+
+    use crate::helpers::table::Tables;
+    use crate::uv::fix;
+
+    fn evaluate(start: &str) -> String {
+        let root_ast = parse(start).into_syntax().clone_for_update();
+        let count = root_ast.children_with_tokens().count();
+        let tables = Tables::from_ast(&root_ast, false);
+        fix(&tables, false);
+        let entries = tables
+            .table_set
+            .iter()
+            .flat_map(|e| e.borrow().clone())
+            .collect::<Vec<SyntaxElement>>();
+        root_ast.splice_children(0..count, entries);
+        let opt = Options {
+            column_width: 1,
+            ..Options::default()
+        };
+        format_syntax(root_ast, opt)
+    }
+
+    #[rstest]
+    #[case::no_uv(
+        indoc ! {r""},
+        "\n",
+    )]
+    #[case::dependencies(
+        indoc ! {r#"
+    [tool.uv]
+    dev-dependencies = ["pytest>=8.0.0", "Coverage[toml]>=7"]
+    override-dependencies = ["b==1.0", "a"]
+    workspace.members = ["packages/*", "apps/*"]
+
+    [tool.uv.workspace]
+    exclude = ["packages/b", "packages/a"]
+    "#},
+        indoc ! {r#"
+    [tool.uv]
+    dev-dependencies = [
+      "coverage[toml]>=7",
+      "pytest>=8",
+    ]
+    override-dependencies = [
+      "a",
+      "b==1",
+    ]
+    workspace.members = [
+      "apps/*",
+      "packages/*",
+    ]
+
+    [tool.uv.workspace]
+    exclude = [
+      "packages/a",
+      "packages/b",
+    ]
+    "#},
+    )]
+    #[case::not_requirements(
+        indoc ! {r#"
+    [tool.uv]
+    dev-dependencies = ["b c", "-e ./a", "A>=1.0"]
+    "#},
+        indoc ! {r#"
+    [tool.uv]
+    dev-dependencies = [
+      "-e ./a",
+      "a>=1",
+      "b c",
+    ]
+    "#},
+    )]
+    #[case::sources(
+        indoc ! {r#"
+    [tool.uv.sources]
+    # from the workspace
+    Zope_Interface = { workspace = true }
+    httpx = { git = "https://github.com/encode/httpx", tag = "0.27.0" }
+    "a.b" = { path = "a" }
+    "#},
+        indoc ! {r#"
+    [tool.uv.sources]
+    "a.b" = { path = "a" }
+    httpx = { git = "https://github.com/encode/httpx", tag = "0.27.0" }
+    # from the workspace
+    Zope_Interface = { workspace = true }
+    "#},
+    )]
+    #[case::default_index_last(
+        indoc ! {r#"
+    [[tool.uv.index]]
+    name = "pypi"
+    url = "https://pypi.org/simple"
+    default = true
+
+    [[tool.uv.index]]
+    name = "internal"
+    url = "https://internal.example.com/simple"
+
+    [[tool.uv.index]]
+    name = "torch"
+    url = "https://download.pytorch.org/whl/cpu"
+    explicit = true
+    "#},
+        indoc ! {r#"
+    [[tool.uv.index]]
+    name = "internal"
+    url = "https://internal.example.com/simple"
+
+    [[tool.uv.index]]
+    name = "torch"
+    url = "https://download.pytorch.org/whl/cpu"
+    explicit = true
+
+    [[tool.uv.index]]
+    name = "pypi"
+    url = "https://pypi.org/simple"
+    default = true
+    "#},
+    )]
+    fn test_format_uv(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(start), expected);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::{Display, Formatter};
+
+use taplo::dom::node::DomNode;
+use taplo::dom::Error;
+use taplo::parser::parse;
+use taplo::rowan::{TextRange, TextSize};
+use taplo::syntax::SyntaxElement;
+use taplo::syntax::SyntaxKind::{ENTRY, KEY, TABLE_ARRAY_HEADER, TABLE_HEADER};
+
+use crate::helpers::table::get_table_name;
+
+/// A definition pip (and any other TOML reader) would reject, located by its byte range within the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub message: String,
+    pub range: TextRange,
+}
+
+impl Problem {
+    /// One based line and column of the start of the problem.
+    pub fn position(&self, content: &str) -> (usize, usize) {
+        line_column(content, self.range.start())
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Detect duplicate keys and conflicting table definitions (e.g. `[project]` twice, or `urls.x` next to
+/// `[project.urls]`) that the table join of the formatter would otherwise silently merge.
+pub fn validate(content: &str) -> Vec<Problem> {
+    let dom = parse(content).into_dom();
+    let Err(errors) = dom.validate() else {
+        return vec![];
+    };
+    let mut problems = errors.filter_map(|e| to_problem(&e, content)).collect::<Vec<Problem>>();
+    problems.sort_by_key(|p| p.range.start());
+    problems.dedup();
+    problems
+}
+
+/// Human-readable summary of the problems, one per line with its location.
+pub fn describe(problems: &[Problem], content: &str) -> String {
+    problems
+        .iter()
+        .map(|p| {
+            let (line, column) = p.position(content);
+            format!("{line}:{column}: {p}")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn to_problem(error: &Error, content: &str) -> Option<Problem> {
+    match error {
+        Error::ConflictingKeys { key, other } => {
+            let (at, first) = (key.syntax()?, other.syntax()?);
+            let (line, _) = line_column(content, first.text_range().start());
+            let name = full_name(at);
+            let message = match (is_header(at), is_header(first)) {
+                (true, true) => format!("table [{name}] defined more than once, first at line {line}"),
+                (true, false) | (false, true) => {
+                    format!("table [{name}] conflicts with the dotted key defined at line {line}")
+                }
+                (false, false) => format!("duplicate key {name}, first defined at line {line}"),
+            };
+            Some(Problem {
+                message,
+                range: at.text_range(),
+            })
+        }
+        Error::ExpectedTable { not_table, required_by } => {
+            let at = required_by.syntax()?;
+            let (line, _) = line_column(content, not_table.syntax()?.text_range().start());
+            Some(Problem {
+                message: format!(
+                    "{} requires a table, but a value is defined at line {line}",
+                    full_name(at)
+                ),
+                range: at.text_range(),
+            })
+        }
+        Error::ExpectedArrayOfTables {
+            not_array_of_tables,
+            required_by,
+        } => {
+            let at = required_by.syntax()?;
+            let (line, _) = line_column(content, not_array_of_tables.syntax()?.text_range().start());
+            Some(Problem {
+                message: format!(
+                    "{} requires an array of tables, but a table is defined at line {line}",
+                    full_name(at)
+                ),
+                range: at.text_range(),
+            })
+        }
+        Error::InvalidEscapeSequence { string } => Some(Problem {
+            message: String::from("string contains an invalid escape sequence"),
+            range: string.text_range(),
+        }),
+        Error::UnexpectedSyntax { .. } | Error::Query(_) => None,
+    }
+}
+
+fn is_header(ident: &SyntaxElement) -> bool {
+    ident
+        .parent()
+        .and_then(|key| key.parent())
+        .is_some_and(|p| [TABLE_HEADER, TABLE_ARRAY_HEADER].contains(&p.kind()))
+}
+
+/// The dotted name of the key the identifier belongs to, including the table the entry is defined in.
+fn full_name(ident: &SyntaxElement) -> String {
+    let Some(key) = ident.parent().filter(|k| k.kind() == KEY) else {
+        return ident.to_string();
+    };
+    let end = ident.text_range().end() - key.text_range().start();
+    let name = key.text().slice(..end).to_string().trim().to_string();
+    let Some(entry) = key.parent().filter(|e| e.kind() == ENTRY) else {
+        return name;
+    };
+    let table = entry
+        .siblings_with_tokens(taplo::rowan::Direction::Prev)
+        .find(|e| [TABLE_HEADER, TABLE_ARRAY_HEADER].contains(&e.kind()))
+        .map(|e| get_table_name(&e))
+        .unwrap_or_default();
+    if table.is_empty() {
+        name
+    } else {
+        format!("{table}.{name}")
+    }
+}
+
+pub fn line_column(content: &str, offset: TextSize) -> (usize, usize) {
+    let before = &content[..usize::from(offset).min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |at| at + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use rstest::rstest;
+
+    use crate::validate::{describe, validate};
+
+    #[rstest]
+    #[case::valid(
+        indoc ! {r#"
+    [project]
+    name = "a"
+    urls.x = "b"
+    "#},
+        ""
+    )]
+    #[case::duplicate_table(
+        indoc ! {r#"
+    [build-system]
+    requires = []
+    [build-system]
+    build-backend = "x"
+    "#},
+        "3:2: table [build-system] defined more than once, first at line 1"
+    )]
+    #[case::duplicate_key(
+        indoc ! {r#"
+    [project]
+    name = "a"
+    name = "b"
+    "#},
+        "3:1: duplicate key project.name, first defined at line 2"
+    )]
+    #[case::dotted_key_and_table(
+        indoc ! {r#"
+    [project]
+    urls.x = "a"
+    [project.urls]
+    y = "b"
+    "#},
+        "3:10: table [project.urls] conflicts with the dotted key defined at line 2"
+    )]
+    #[case::value_and_table(
+        indoc ! {r"
+    [tool]
+    a = 1
+    [tool.a.b]
+    c = 1
+    "},
+        "3:7: tool.a requires a table, but a value is defined at line 2"
+    )]
+    fn test_validate(#[case] start: &str, #[case] expected: &str) {
+        assert_eq!(describe(&validate(start), start), expected);
+    }
+}
@@ -2,9 +2,24 @@
 
 from __future__ import annotations
 
-from ._lib import Settings, format_toml
+from ._lib import (
+    Diagnostic,
+    Settings,
+    diagnose,
+    diagnostics_report,
+    format_range,
+    format_toml,
+    format_toml_edits,
+    serve_lsp,
+)
 
 __all__ = [
+    "Diagnostic",
     "Settings",
+    "diagnose",
+    "diagnostics_report",
+    "format_range",
     "format_toml",
+    "format_toml_edits",
+    "serve_lsp",
 ]
@@ -1,3 +1,5 @@
+from typing import Literal
+
 class Settings:
     def __init__(
         self,
@@ -7,6 +9,37 @@ class Settings:
         keep_full_version: bool,
         max_supported_python: tuple[int, int],
         min_supported_python: tuple[int, int],
+        table_format: str = "keep",
+        expand_tables: list[str] = ...,
+        collapse_tables: list[str] = ...,
+        table_order: list[str] = ...,
+        table_order_mode: str = "prepend",
+        sort_unknown_tools: bool = False,
+        table_array_sort_keys: dict[str, str] = ...,
+        lenient: bool = False,
+        line_ending: str = "auto",
+        indent_style: str = "space",
+        align_entries: bool = False,
+        align_comments: bool = True,
+        align_single_comments: bool = True,
+        array_trailing_comma: bool = True,
+        array_auto_expand: bool = True,
+        array_auto_collapse: bool = False,
+        compact_arrays: bool = False,
+        compact_inline_tables: bool = False,
+        compact_entries: bool = False,
+        indent_tables: bool = False,
+        indent_entries: bool = False,
+        inline_table_expand: bool = True,
+        trailing_newline: bool = True,
+        allowed_blank_lines: int = 1,
+        blank_lines_between_groups: int = 1,
+        blank_lines_between_sub_tables: int | None = None,
+        keep_banner_comments: bool = False,
+        self_check: bool = False,
+        split_pytest_addopts: bool = False,
+        sort_pytest_filterwarnings: bool = False,
+        migrate_poetry: bool = False,
     ) -> None: ...
     @property
     def column_width(self) -> int: ...
@@ -18,5 +51,100 @@ class Settings:
     def max_supported_python(self) -> tuple[int, int]: ...
     @property
     def min_supported_python(self) -> tuple[int, int]: ...
+    @property
+    def table_format(self) -> str: ...
+    @property
+    def expand_tables(self) -> list[str]: ...
+    @property
+    def collapse_tables(self) -> list[str]: ...
+    @property
+    def table_order(self) -> list[str]: ...
+    @property
+    def table_order_mode(self) -> str: ...
+    @property
+    def sort_unknown_tools(self) -> bool: ...
+    @property
+    def table_array_sort_keys(self) -> dict[str, str]: ...
+    @property
+    def lenient(self) -> bool: ...
+    @property
+    def line_ending(self) -> str: ...
+    @property
+    def indent_style(self) -> str: ...
+    @property
+    def align_entries(self) -> bool: ...
+    @property
+    def align_comments(self) -> bool: ...
+    @property
+    def align_single_comments(self) -> bool: ...
+    @property
+    def array_trailing_comma(self) -> bool: ...
+    @property
+    def array_auto_expand(self) -> bool: ...
+    @property
+    def array_auto_collapse(self) -> bool: ...
+    @property
+    def compact_arrays(self) -> bool: ...
+    @property
+    def compact_inline_tables(self) -> bool: ...
+    @property
+    def compact_entries(self) -> bool: ...
+    @property
+    def indent_tables(self) -> bool: ...
+    @property
+    def indent_entries(self) -> bool: ...
+    @property
+    def inline_table_expand(self) -> bool: ...
+    @property
+    def trailing_newline(self) -> bool: ...
+    @property
+    def allowed_blank_lines(self) -> int: ...
+    @property
+    def blank_lines_between_groups(self) -> int: ...
+    @property
+    def blank_lines_between_sub_tables(self) -> int | None: ...
+    @property
+    def keep_banner_comments(self) -> bool: ...
+    @property
+    def self_check(self) -> bool: ...
+    @property
+    def split_pytest_addopts(self) -> bool: ...
+    @property
+    def sort_pytest_filterwarnings(self) -> bool: ...
+    @property
+    def migrate_poetry(self) -> bool: ...
+
+class Diagnostic:
+    @property
+    def code(self) -> str: ...
+    @property
+    def severity(self) -> Literal["error", "warning"]: ...
+    @property
+    def message(self) -> str: ...
+    @property
+    def start(self) -> int: ...
+    @property
+    def end(self) -> int: ...
+    @property
+    def line(self) -> int: ...
+    @property
+    def column(self) -> int: ...
+    @property
+    def end_line(self) -> int: ...
+    @property
+    def end_column(self) -> int: ...
 
 def format_toml(content: str, settings: Settings) -> str: ...
+def format_toml_edits(content: str, settings: Settings) -> list[tuple[int, int, str]]: ...
+def format_range(
+    content: str, settings: Settings, start: int, end: int, *, lines: bool = False
+) -> list[tuple[int, int, str]]: ...
+def diagnose(content: str, settings: Settings) -> list[Diagnostic]: ...
+def diagnostics_report(
+    content: str,
+    settings: Settings,
+    *,
+    output_format: Literal["json", "sarif"] = "json",
+    path: str = "pyproject.toml",
+) -> str: ...
+def serve_lsp(settings: Settings) -> None: ...
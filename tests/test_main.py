@@ -1,10 +1,11 @@
 from __future__ import annotations
 
+import json
 from textwrap import dedent
 
 import pytest
 
-from pyproject_fmt_rust import Settings, format_toml
+from pyproject_fmt_rust import Settings, diagnose, diagnostics_report, format_toml, format_toml_edits
 
 
 @pytest.mark.parametrize(
@@ -78,3 +79,47 @@ def test_format_toml(start: str, expected: str) -> None:
     )
     res = format_toml(dedent(start), settings)
     assert res == dedent(expected)
+
+
+def test_format_toml_duplicate_table() -> None:
+    settings = Settings(
+        column_width=120,
+        indent=4,
+        keep_full_version=True,
+        min_supported_python=(3, 7),
+        max_supported_python=(3, 8),
+    )
+    with pytest.raises(ValueError, match=r"^3:2: table \[build-system\] defined more than once, first at line 1$"):
+        format_toml("[build-system]\nrequires = []\n[build-system]\nbuild-backend = 'a'\n", settings)
+
+
+def test_diagnose() -> None:
+    settings = Settings(
+        column_width=120,
+        indent=4,
+        keep_full_version=True,
+        min_supported_python=(3, 9),
+        max_supported_python=(3, 13),
+    )
+    content = '[project]\nrequires-python = ">=4"\n'
+    (diagnostic,) = diagnose(content, settings)
+    assert (diagnostic.code, diagnostic.severity) == ("PF103", "warning")
+    assert (diagnostic.line, diagnostic.column, diagnostic.end_line, diagnostic.end_column) == (2, 19, 2, 24)
+    assert content[diagnostic.start : diagnostic.end] == '">=4"'
+    report = json.loads(diagnostics_report(content, settings, output_format="sarif"))
+    assert report["runs"][0]["results"][0]["ruleId"] == "PF103"
+
+
+def test_format_toml_edits() -> None:
+    settings = Settings(
+        column_width=120,
+        indent=2,
+        keep_full_version=False,
+        min_supported_python=(3, 9),
+        max_supported_python=(3, 13),
+    )
+    content = '[tool.a]\na=[1]\n\n[project]\nname = "a"\n'
+    result = content.encode()
+    for start, end, new_text in reversed(format_toml_edits(content, settings)):
+        result = result[:start] + new_text.encode() + result[end:]
+    assert result.decode() == format_toml(content, settings)